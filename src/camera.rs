@@ -0,0 +1,112 @@
+use super::{settings::Settings, tile_glyph, tileset, Map, Position, Renderable};
+use rltk::{Point, Rltk, RGB};
+use specs::prelude::*;
+
+// マップを描く画面の範囲. この下はdraw_uiのログ欄
+pub const VIEW_WIDTH: i32 = 80;
+pub const VIEW_HEIGHT: i32 = 43;
+// ログ欄の高さ(枠を含む). 画面全体はマップとログ欄を合わせた大きさ
+pub const LOG_HEIGHT: i32 = 7;
+pub const SCREEN_WIDTH: i32 = VIEW_WIDTH;
+pub const SCREEN_HEIGHT: i32 = VIEW_HEIGHT + LOG_HEIGHT;
+
+// コンソールのレイヤー. 番号が大きいほど上に描かれる
+// ASCIIのマップ, タイル画像のマップ, ログ欄やメニューの順
+pub const MAP_LAYER: usize = 0;
+pub const TILE_LAYER: usize = 1;
+pub const UI_LAYER: usize = 2;
+
+/// 画面の左上に映るワールド座標
+/// playerが真ん中に来るようにして, マップの端では止める
+/// マップが画面より小さい方向は, マップを画面の真ん中に置く
+pub fn view_origin(ecs: &World) -> Point {
+    let player_pos = ecs.fetch::<Point>();
+    let map = ecs.fetch::<Map>();
+    Point::new(
+        axis_origin(player_pos.x, VIEW_WIDTH, map.width),
+        axis_origin(player_pos.y, VIEW_HEIGHT, map.height),
+    )
+}
+
+fn axis_origin(player: i32, view: i32, map: i32) -> i32 {
+    if map <= view {
+        -(view - map) / 2
+    } else {
+        i32::clamp(player - view / 2, 0, map - view)
+    }
+}
+
+fn on_screen(screen: Point) -> bool {
+    (0..VIEW_WIDTH).contains(&screen.x) && (0..VIEW_HEIGHT).contains(&screen.y)
+}
+
+/// 画面座標からワールド座標へ. マップの外ならNone
+pub fn screen_to_world(ecs: &World, screen: Point) -> Option<Point> {
+    if !on_screen(screen) {
+        return None;
+    }
+    let origin = view_origin(ecs);
+    let map = ecs.fetch::<Map>();
+    let world = Point::new(screen.x + origin.x, screen.y + origin.y);
+    if world.x < 0 || world.x >= map.width || world.y < 0 || world.y >= map.height {
+        return None;
+    }
+    Some(world)
+}
+
+/// ワールド座標から画面座標へ. 画面に映ってなければNone
+pub fn world_to_screen(ecs: &World, world: Point) -> Option<Point> {
+    let origin = view_origin(ecs);
+    let screen = Point::new(world.x - origin.x, world.y - origin.y);
+    if !on_screen(screen) {
+        return None;
+    }
+    Some(screen)
+}
+
+/// playerのまわりのマップとentityを画面に描く
+/// タイル画像モードなら, 絵のあるマスはタイルのレイヤーに描きなおす
+pub fn render_camera(ecs: &World, ctx: &mut Rltk) {
+    ctx.set_active_console(MAP_LAYER);
+    let origin = view_origin(ecs);
+    let map = ecs.fetch::<Map>();
+
+    for sy in 0..VIEW_HEIGHT {
+        for sx in 0..VIEW_WIDTH {
+            let x = sx + origin.x;
+            let y = sy + origin.y;
+            if x < 0 || x >= map.width || y < 0 || y >= map.height {
+                continue;
+            }
+
+            // タイルの種類に応じてタイルを描く
+            let idx = map.xy_idx(x, y);
+            if map.revealed_tiles[idx] {
+                let (glyph, mut fg) = tile_glyph(map.tiles[idx]);
+                if map.visible_tiles[idx] {
+                    // 明かりの色で染める
+                    fg = fg * map.light[idx];
+                } else {
+                    fg = fg.to_greyscale()
+                }
+                ctx.set(sx, sy, fg, RGB::from_f32(0., 0., 0.), glyph);
+            }
+        }
+    }
+
+    let positions = ecs.read_storage::<Position>();
+    let renderables = ecs.read_storage::<Renderable>();
+    let mut data = (&positions, &renderables).join().collect::<Vec<_>>();
+    data.sort_by_key(|&a| std::cmp::Reverse(a.1.render_order));
+    for (pos, render) in data.iter() {
+        let idx = map.xy_idx(pos.x, pos.y);
+        let screen = Point::new(pos.x - origin.x, pos.y - origin.y);
+        if map.visible_tiles[idx] && on_screen(screen) {
+            ctx.set(screen.x, screen.y, render.fg, render.bg, render.glyph)
+        }
+    }
+
+    if ecs.fetch::<Settings>().tiles {
+        tileset::render_tiles(ecs, ctx);
+    }
+}
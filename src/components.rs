@@ -2,12 +2,17 @@ use rltk::RGB;
 use specs::prelude::*;
 use specs_derive::*;
 
-#[derive(Component)]
+// 動いたり消えたりしたらMapIndexingSystemに知らせが行く
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Position {
     pub x: i32,
     pub y: i32,
 }
 
+impl Component for Position {
+    type Storage = FlaggedStorage<Self, DenseVecStorage<Self>>;
+}
+
 #[derive(Component)]
 pub struct Renderable {
     pub glyph: rltk::FontCharType, // @さんとか
@@ -37,9 +42,34 @@ pub struct Viewshed {
     pub dirty: bool,
 }
 
-#[derive(Component, Debug)]
+// ドアの開け閉めでついたり外れたりするので, これも知らせが行く
+#[derive(Debug)]
 pub struct BlocksTile {}
 
+impl Component for BlocksTile {
+    type Storage = FlaggedStorage<Self, DenseVecStorage<Self>>;
+}
+
+// まわりを照らす. 松明とかplayerとか光るmonsterとか
+#[derive(Component, Debug)]
+pub struct LightSource {
+    pub radius: i32,
+    pub colour: RGB,
+}
+
+// これがついてるentityの向こう側は見えない
+#[derive(Debug)]
+pub struct BlocksVisibility {}
+
+impl Component for BlocksVisibility {
+    type Storage = FlaggedStorage<Self, DenseVecStorage<Self>>;
+}
+
+#[derive(Component, Debug)]
+pub struct Door {
+    pub open: bool,
+}
+
 #[derive(Component, Debug)]
 pub struct CombatStats {
     pub max_hp: i32,
@@ -55,25 +85,48 @@ pub struct WantsToMelee {
 
 #[derive(Component, Debug)]
 pub struct SufferDamage {
-    pub amount: Vec<i32>,
+    // (ダメージ, 与えたentity). 地形のダメージみたいに誰のせいでもないときはNone
+    pub amount: Vec<(i32, Option<Entity>)>,
 }
 
 impl SufferDamage {
-    pub fn new_damage(store: &mut WriteStorage<SufferDamage>, victim: Entity, amount: i32) {
+    pub fn new_damage(
+        store: &mut WriteStorage<SufferDamage>,
+        victim: Entity,
+        amount: i32,
+        source: Option<Entity>,
+    ) {
         if let Some(suffering) = store.get_mut(victim) {
-            suffering.amount.push(amount);
+            suffering.amount.push((amount, source));
         } else {
             let dmg = SufferDamage {
-                amount: vec![amount],
+                amount: vec![(amount, source)],
             };
             store.insert(victim, dmg).expect("Unable to insert damage");
         }
     }
 }
 
+// このターンに新しいタイルに入った. 地形の効果を受ける
+#[derive(Component, Debug)]
+pub struct EnteredTile {}
+
 #[derive(Component, Debug)]
 pub struct Item {}
 
+// wizardモードの無敵. ダメージを受けない
+#[derive(Component, Debug)]
+pub struct GodMode {}
+
+// 泳げる. 深い水にも入れる
+#[derive(Component, Debug)]
+pub struct Swimmer {}
+
+// playerが自分で置いたり投げたりしたアイテム. 自動で拾いなおさないし, 見つけた数にも数えない
+// 拾ったら外れる
+#[derive(Component, Debug)]
+pub struct DroppedByPlayer {}
+
 #[derive(Component, Debug)]
 pub struct ProvidesHealing {
     pub heal_amount: i32,
@@ -82,9 +135,12 @@ pub struct ProvidesHealing {
 #[derive(Component, Debug, Clone)]
 pub struct InBackpack {
     pub owner: Entity,
+    // メニューで選ぶときの文字. 0が'a'. 持ってるあいだは変わらない
+    pub slot: usize,
 }
 
 // だれがItemを拾いたいか？
+// 拾われるアイテムのほうにつける. 1ターンにいくつも拾えるように
 #[derive(Component, Debug, Clone)]
 pub struct WantsToPickupItem {
     pub collected_by: Entity,
@@ -102,9 +158,21 @@ pub struct WantsToDropItem {
     pub item: Entity,
 }
 
+#[derive(Component, Debug)]
+pub struct WantsToThrowItem {
+    pub item: Entity,
+    pub target: rltk::Point,
+}
+
 #[derive(Component, Debug)]
 pub struct Consumable {}
 
+// 同じ名前のアイテムは1つにまとめて持つ. countが何個あるか
+#[derive(Component, Debug)]
+pub struct Stackable {
+    pub count: i32,
+}
+
 #[derive(Component, Debug)]
 pub struct ProvideHealing {
     pub heal_amount: i32,
@@ -120,6 +188,10 @@ pub struct InflictsDamage {
     pub damage: i32,
 }
 
+// まっすぐ飛んで, 途中でぶつかったものに当たる
+#[derive(Component, Debug)]
+pub struct Bolt {}
+
 #[derive(Component, Debug)]
 pub struct AreaOfEffect {
     pub radius: i32,
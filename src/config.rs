@@ -0,0 +1,15 @@
+use std::path::PathBuf;
+
+const APP_DIR: &str = "rougulike_rust";
+
+/// ユーザーごとに残すデータの場所. morgueファイルとか
+/// Linuxなら ~/.local/share/rougulike_rust/<name>
+pub fn data_file(name: &str) -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join(APP_DIR).join(name))
+}
+
+/// ユーザーごとの設定ファイルの場所
+/// Linuxなら ~/.config/rougulike_rust/<name>
+pub fn config_file(name: &str) -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(APP_DIR).join(name))
+}
@@ -1,20 +1,82 @@
-use super::{gamelog::GameLog, CombatStats, Name, Player, SufferDamage};
+use super::{
+    gamelog::GameLog, statistics::Statistics, tile_name, CombatStats, GodMode, Map, Name, Player,
+    Position, SufferDamage,
+};
 use rltk::console;
 use specs::prelude::*;
 
 pub struct DamageSystem {}
 
 impl<'a> System<'a> for DamageSystem {
+    #[allow(clippy::type_complexity)]
     type SystemData = (
+        Entities<'a>,
+        ReadExpect<'a, Entity>,
+        ReadExpect<'a, Map>,
+        WriteExpect<'a, Statistics>,
         WriteStorage<'a, CombatStats>,
         WriteStorage<'a, SufferDamage>,
+        ReadStorage<'a, Name>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, GodMode>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut stats, mut damage) = data;
+        let (
+            entities,
+            player_entity,
+            map,
+            mut statistics,
+            mut stats,
+            mut damage,
+            names,
+            positions,
+            god_mode,
+        ) = data;
 
-        for (mut stats, damage) in (&mut stats, &damage).join() {
-            stats.hp -= damage.amount.iter().sum::<i32>();
+        // 無敵のentityへのダメージは捨てる
+        for (entity, stats, damage, _) in (&entities, &mut stats, &damage, !&god_mode).join() {
+            let was_alive = stats.hp > 0;
+            let mut hit_by_player = false;
+            for (amount, source) in damage.amount.iter() {
+                stats.hp -= amount;
+                // キャラクター画面用に, playerが与えたダメージと受けたダメージを数えておく
+                if entity == *player_entity {
+                    statistics.damage_taken += amount;
+                } else if *source == Some(*player_entity) {
+                    statistics.damage_dealt += amount;
+                    hit_by_player = true;
+                }
+            }
+            // このターンにplayerの攻撃を受けて倒れたらplayerが倒したことにする
+            if was_alive && stats.hp < 1 && hit_by_player {
+                statistics.kills += 1;
+                let name = names
+                    .get(entity)
+                    .map_or("???".to_string(), |n| n.name.clone());
+                statistics.kill_list.push(name);
+            }
+            // playerが倒れたら, とどめを刺したものを覚えておく
+            if was_alive && stats.hp < 1 && entity == *player_entity {
+                let last_source = damage.amount.last().and_then(|(_, source)| *source);
+                statistics.cause_of_death = Some(match last_source {
+                    Some(source) if source == *player_entity => {
+                        "Killed by their own hand".to_string()
+                    }
+                    Some(source) => match names.get(source) {
+                        Some(name) => format!("Killed by {}", name.name),
+                        None => "Killed by something unseen".to_string(),
+                    },
+                    // 出どころのないダメージは足元の地形から
+                    None => match positions.get(entity) {
+                        Some(pos) => {
+                            let tile = map.tiles[map.xy_idx(pos.x, pos.y)];
+                            format!("Killed by {}", tile_name(tile).to_lowercase())
+                        }
+                        None => "Killed by the dungeon".to_string(),
+                    },
+                });
+            }
         }
 
         damage.clear();
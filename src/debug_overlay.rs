@@ -0,0 +1,164 @@
+use super::{
+    camera,
+    gui::highlight,
+    monster_ai_system::{choose_action, MonsterAction},
+    Confusion, Map, Monster, Position, Swimmer, Viewshed,
+};
+use rltk::{Point, Rltk, RGB};
+use specs::prelude::*;
+
+// wizardのコンソールから切り替える, マップの中身を見るための表示
+// 描くだけでworldは何も変えない
+
+/// どの表示を出すか
+#[derive(Default, Clone, Copy, Debug)]
+pub struct DebugOverlays {
+    pub blocked: bool,
+    pub tile_content: bool,
+    pub monster_paths: bool,
+    pub viewsheds: bool,
+    pub rooms: bool,
+}
+
+pub const OVERLAY_NAMES: &str = "blocked, content, paths, viewsheds, rooms";
+
+impl DebugOverlays {
+    /// 名前の表示を切り替える. 出るようになったらtrue
+    pub fn toggle(&mut self, name: &str) -> Result<bool, String> {
+        let flag = match name {
+            "blocked" => &mut self.blocked,
+            "content" => &mut self.tile_content,
+            "paths" => &mut self.monster_paths,
+            "viewsheds" => &mut self.viewsheds,
+            "rooms" => &mut self.rooms,
+            _ => {
+                return Err(format!(
+                    "Unknown overlay '{}'. Try: {}",
+                    name, OVERLAY_NAMES
+                ))
+            }
+        };
+        *flag = !*flag;
+        Ok(*flag)
+    }
+
+    fn any(&self) -> bool {
+        self.blocked || self.tile_content || self.monster_paths || self.viewsheds || self.rooms
+    }
+}
+
+/// monsterがいまplayerに向かって歩く道. MonsterAIと同じchoose_actionで決める
+/// 混乱してたり, 見えてなかったり, 隣にいて殴るmonsterは歩かないので入れない
+pub fn monster_paths(ecs: &World) -> Vec<Vec<Point>> {
+    let map = ecs.fetch::<Map>();
+    let player_pos = ecs.fetch::<Point>();
+    let entities = ecs.entities();
+    let monsters = ecs.read_storage::<Monster>();
+    let positions = ecs.read_storage::<Position>();
+    let viewsheds = ecs.read_storage::<Viewshed>();
+    let confused = ecs.read_storage::<Confusion>();
+    let swimmers = ecs.read_storage::<Swimmer>();
+
+    let mut paths = Vec::new();
+    for (entity, _monster, pos, viewshed) in (&entities, &monsters, &positions, &viewsheds).join() {
+        let action = choose_action(
+            &map,
+            *pos,
+            viewshed,
+            *player_pos,
+            confused.contains(entity),
+            swimmers.contains(entity),
+        );
+        if let MonsterAction::Move(steps) = action {
+            paths.push(
+                steps
+                    .iter()
+                    .skip(1)
+                    .map(|idx| Point::new(*idx as i32 % map.width, *idx as i32 / map.width))
+                    .collect(),
+            );
+        }
+    }
+    paths
+}
+
+/// 画面に映ってるマスならそこに色をつける
+fn tint(ecs: &World, ctx: &mut Rltk, point: Point, colour: RGB) {
+    if let Some(screen) = camera::world_to_screen(ecs, point) {
+        highlight(ctx, screen.x, screen.y, colour);
+    }
+}
+
+/// 切り替えてある表示を今のレイヤーに描く. マップのあと, メニューより先に呼ぶ
+pub fn render_overlays(ecs: &World, ctx: &mut Rltk) {
+    let overlays = *ecs.fetch::<DebugOverlays>();
+    if !overlays.any() {
+        return;
+    }
+    let map = ecs.fetch::<Map>();
+    let point_at = |idx: usize| Point::new(idx as i32 % map.width, idx as i32 / map.width);
+
+    if overlays.viewsheds {
+        let monsters = ecs.read_storage::<Monster>();
+        let viewsheds = ecs.read_storage::<Viewshed>();
+        for (_monster, viewshed) in (&monsters, &viewsheds).join() {
+            for point in viewshed.visible_tiles.iter() {
+                tint(ecs, ctx, *point, RGB::named(rltk::BLUE));
+            }
+        }
+    }
+
+    if overlays.blocked {
+        for (idx, blocked) in map.blocked.iter().enumerate() {
+            if *blocked {
+                tint(ecs, ctx, point_at(idx), RGB::named(rltk::RED));
+            }
+        }
+    }
+
+    if overlays.monster_paths {
+        for path in monster_paths(ecs).iter() {
+            for point in path.iter() {
+                tint(ecs, ctx, *point, RGB::named(rltk::YELLOW));
+            }
+        }
+    }
+
+    if overlays.tile_content {
+        for (idx, content) in map.tile_content.iter().enumerate() {
+            if content.is_empty() {
+                continue;
+            }
+            if let Some(screen) = camera::world_to_screen(ecs, point_at(idx)) {
+                let count = if content.len() > 9 {
+                    '+'
+                } else {
+                    (b'0' + content.len() as u8) as char
+                };
+                ctx.set(
+                    screen.x,
+                    screen.y,
+                    RGB::named(rltk::WHITE),
+                    RGB::named(rltk::DARK_GREEN),
+                    rltk::to_cp437(count),
+                );
+            }
+        }
+    }
+
+    // 部屋の番号は部屋の中の左上のマスに出す
+    if overlays.rooms {
+        for (i, room) in map.rooms.iter().enumerate() {
+            let corner = Point::new(room.x1 + 1, room.y1 + 1);
+            if let Some(screen) = camera::world_to_screen(ecs, corner) {
+                ctx.print_color(
+                    screen.x,
+                    screen.y,
+                    RGB::named(rltk::BLACK),
+                    RGB::named(rltk::CYAN),
+                    i.to_string(),
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use super::{
+    AreaOfEffect, Bolt, CombatStats, Confusion, Consumable, Door, InflictsDamage, Item,
+    LightSource, Name, ProvidesHealing, Ranged, Stackable,
+};
+use serde::Deserialize;
+use specs::prelude::*;
+use std::collections::HashMap;
+
+/// 画面に出すアイテムの名前. まとめて持ってるなら "Red Potion x3"
+pub fn item_name(ecs: &World, item: Entity) -> String {
+    let names = ecs.read_storage::<Name>();
+    let stackables = ecs.read_storage::<Stackable>();
+    let name = names
+        .get(item)
+        .map_or("???".to_string(), |n| n.name.clone());
+    match stackables.get(item) {
+        Some(stack) if stack.count > 1 => format!("{} x{}", name, stack.count),
+        _ => name,
+    }
+}
+
+/// HPの割合をことばにする. 数字は見せない
+pub fn health_word(stats: &CombatStats) -> &'static str {
+    if stats.hp >= stats.max_hp {
+        return "Unhurt";
+    }
+    let ratio = stats.hp as f32 / stats.max_hp as f32;
+    if ratio > 0.75 {
+        "Lightly wounded"
+    } else if ratio > 0.5 {
+        "Wounded"
+    } else if ratio > 0.25 {
+        "Badly wounded"
+    } else {
+        "Almost dead"
+    }
+}
+
+/// entityにいまかかってる状態
+pub fn statuses(ecs: &World, entity: Entity) -> Vec<String> {
+    let items = ecs.read_storage::<Item>();
+    let confused = ecs.read_storage::<Confusion>();
+    let doors = ecs.read_storage::<Door>();
+    let lights = ecs.read_storage::<LightSource>();
+
+    let mut result = Vec::new();
+    // アイテムのConfusionは効果なので, ここでは状態として扱わない
+    if let Some(confusion) = confused.get(entity) {
+        if items.get(entity).is_none() {
+            result.push(format!("Confused ({} turns)", confusion.turns));
+        }
+    }
+    if let Some(door) = doors.get(entity) {
+        result.push(if door.open { "Open" } else { "Closed" }.to_string());
+    }
+    if lights.get(entity).is_some() {
+        result.push("Gives off light".to_string());
+    }
+    result
+}
+
+/// アイテムを使うと何が起こるか. コンポーネントから文をつくる. 1行に1つ
+/// 例: "Deals 20 damage in a radius 3 area, range 6"
+pub fn item_effects(ecs: &World, item: Entity) -> Vec<String> {
+    let healing = ecs.read_storage::<ProvidesHealing>();
+    let damage = ecs.read_storage::<InflictsDamage>();
+    let aoe = ecs.read_storage::<AreaOfEffect>();
+    let bolts = ecs.read_storage::<Bolt>();
+    let ranged = ecs.read_storage::<Ranged>();
+    let confusion = ecs.read_storage::<Confusion>();
+    let consumable = ecs.read_storage::<Consumable>();
+
+    // どこに効くか. 狙って使うアイテムは効果の文のうしろに射程をつける
+    let area = match (aoe.get(item), bolts.get(item)) {
+        (Some(aoe), _) => format!(" in a radius {} area", aoe.radius),
+        (None, Some(_)) => " to the first thing in its path".to_string(),
+        (None, None) => String::new(),
+    };
+    let range = match ranged.get(item) {
+        Some(ranged) => format!(", range {}", ranged.range),
+        None => String::new(),
+    };
+
+    let mut result = Vec::new();
+    if let Some(healing) = healing.get(item) {
+        result.push(format!("Heals {} HP", healing.heal_amount));
+    }
+    if let Some(damage) = damage.get(item) {
+        result.push(format!("Deals {} damage{}{}", damage.damage, area, range));
+    }
+    if let Some(confusion) = confusion.get(item) {
+        let target = if area.is_empty() { " a target" } else { "" };
+        result.push(format!(
+            "Confuses{} for {} turns{}{}",
+            target, confusion.turns, area, range
+        ));
+    }
+    if consumable.get(item).is_some() {
+        result.push("Single use".to_string());
+    }
+    result
+}
+
+// 説明文のデータ. ビルドのときに埋め込む
+const FLAVOUR_RAW: &str = include_str!("../resources/item_flavour.toml");
+const FLAVOUR_WIDTH: usize = 40;
+
+/// アイテムの説明文. なくてもいい. 名前で引く
+#[derive(Deserialize, Default)]
+pub struct FlavourText {
+    #[serde(default)]
+    items: HashMap<String, String>,
+}
+
+impl FlavourText {
+    pub fn load() -> Result<FlavourText, toml::de::Error> {
+        toml::from_str(FLAVOUR_RAW)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.items.get(name).map(|text| text.as_str())
+    }
+}
+
+/// 長い文をwidth文字くらいで折り返す
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.len() + 1 + word.len() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// 持ち物を調べたときの説明. 名前と効果のあとに, あれば説明文
+pub fn inspect_lines(ecs: &World, item: Entity) -> Vec<String> {
+    let mut lines = describe_entity(ecs, item);
+    let flavour = ecs.fetch::<FlavourText>();
+    let text = ecs
+        .read_storage::<Name>()
+        .get(item)
+        .and_then(|name| flavour.get(&name.name).map(|t| t.to_string()));
+    if let Some(text) = text {
+        lines.push(String::new());
+        lines.extend(wrap(&text, FLAVOUR_WIDTH));
+    }
+    lines
+}
+
+/// look modeのパネルに出す説明. 名前, 体力, 状態, アイテムの効果の順
+pub fn describe_entity(ecs: &World, entity: Entity) -> Vec<String> {
+    let names = ecs.read_storage::<Name>();
+    let combat_stats = ecs.read_storage::<CombatStats>();
+
+    let mut lines = Vec::new();
+    if names.get(entity).is_some() {
+        lines.push(item_name(ecs, entity));
+    }
+    if let Some(stats) = combat_stats.get(entity) {
+        lines.push(health_word(stats).to_string());
+    }
+    lines.extend(statuses(ecs, entity));
+    lines.extend(item_effects(ecs, entity));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flavour_text_parses() {
+        let flavour = FlavourText::load().unwrap();
+        assert!(flavour.get("Red Potion").is_some());
+        assert!(flavour.get("Nothing").is_none());
+    }
+
+    #[test]
+    fn wrap_keeps_lines_short() {
+        let lines = wrap("one two three four five six", 9);
+        assert_eq!(lines, vec!["one two", "three", "four five", "six"]);
+    }
+}
@@ -1,3 +1,13 @@
 pub struct GameLog {
     pub entries: Vec<String>,
 }
+
+impl GameLog {
+    /// 古いメッセージを捨てて, 新しいほうからmax_entries個だけ残す
+    pub fn trim(&mut self, max_entries: usize) {
+        if self.entries.len() > max_entries {
+            let excess = self.entries.len() - max_entries;
+            self.entries.drain(..excess);
+        }
+    }
+}
@@ -1,15 +1,27 @@
 use super::{
-    gamelog::GameLog, CombatStats, InBackpack, Map, Name, Player, Position, State, Viewshed,
+    camera, describe,
+    gamelog::GameLog,
+    highscores::{HighScores, SHOWN_SCORES},
+    inventory_system::{blast_tiles, bolt_path, items_at, ItemCategory, MAX_BACKPACK_SLOTS},
+    keymap::{Command, Keymap},
+    settings::Settings,
+    statistics::Statistics,
+    tile_name,
+    wizard::ConsoleInput,
+    AreaOfEffect, Bolt, CombatStats, Confusion, InBackpack, InflictsDamage, Item, Map, Monster,
+    Name, Player, Position, ProvidesHealing, Renderable, State, Viewshed,
 };
-use rltk::{DistanceAlg, Point, Rltk, VirtualKeyCode, RGB};
+use rltk::{Point, Rltk, RGB, RGBA};
 use specs::prelude::*;
 
 pub fn draw_ui(ecs: &World, ctx: &mut Rltk) {
+    // ログ欄はマップを描く範囲のすぐ下
+    let top = camera::VIEW_HEIGHT;
     ctx.draw_box(
         0,
-        43,
-        79,
-        6,
+        top,
+        camera::VIEW_WIDTH - 1,
+        camera::LOG_HEIGHT - 1,
         RGB::named(rltk::WHITE),
         RGB::named(rltk::BLACK),
     );
@@ -20,7 +32,7 @@ pub fn draw_ui(ecs: &World, ctx: &mut Rltk) {
         let health = format!(" HP: {} / {}", stats.hp, stats.max_hp);
         ctx.print_color(
             12,
-            43,
+            top,
             RGB::named(rltk::YELLOW),
             RGB::named(rltk::BLACK),
             &health,
@@ -28,8 +40,8 @@ pub fn draw_ui(ecs: &World, ctx: &mut Rltk) {
 
         ctx.draw_bar_horizontal(
             28,
-            43,
-            51,
+            top,
+            camera::VIEW_WIDTH - 29,
             stats.hp,
             stats.max_hp,
             RGB::named(rltk::RED),
@@ -38,21 +50,24 @@ pub fn draw_ui(ecs: &World, ctx: &mut Rltk) {
     }
 
     let log = ecs.fetch::<GameLog>();
-    let mut y = 44;
-    for s in log.entries.iter().rev() {
-        if y < 49 {
-            ctx.print(2, y, s);
-        }
-        y += 1;
+    let lines = (camera::LOG_HEIGHT - 2) as usize;
+    for (i, s) in log.entries.iter().rev().take(lines).enumerate() {
+        ctx.print(2, top + 1 + i as i32, s);
     }
 
     // マウスで指してるセルの背景に色つける
     // mouse_pos.0: x
     // mouse_pos.1: y
     let mouse_pos = ctx.mouse_pos();
-    ctx.set_bg(mouse_pos.0, mouse_pos.1, RGB::named(rltk::MAGENTA));
+    highlight(ctx, mouse_pos.0, mouse_pos.1, RGB::named(rltk::MAGENTA));
+
+    draw_tooltips(ecs, ctx);
+}
 
-    draw_tooltips(&ecs, ctx);
+/// マスに半透明の色をのせる
+/// UIのレイヤーはマップより上にあるので, 下のASCIIやタイルが透けて見えるようにする
+pub fn highlight(ctx: &mut Rltk, x: i32, y: i32, colour: RGB) {
+    ctx.set_bg(x, y, RGBA::from_f32(colour.r, colour.g, colour.b, 0.6));
 }
 
 fn draw_tooltips(ecs: &World, ctx: &mut Rltk) {
@@ -60,15 +75,17 @@ fn draw_tooltips(ecs: &World, ctx: &mut Rltk) {
     let names = ecs.read_storage::<Name>();
     let positions = ecs.read_storage::<Position>();
 
+    // 画面上のマウスの位置と, それが指してるワールド座標
     let mouse_pos = ctx.mouse_pos();
-    if mouse_pos.0 >= map.width || mouse_pos.1 >= map.height {
-        return;
-    }
+    let mouse_world = match camera::screen_to_world(ecs, Point::new(mouse_pos.0, mouse_pos.1)) {
+        Some(p) => p,
+        None => return,
+    };
     let mut tooltip: Vec<String> = Vec::new();
 
     for (name, position) in (&names, &positions).join() {
         let idx = map.xy_idx(position.x, position.y);
-        if position.x == mouse_pos.0 && position.y == mouse_pos.1 && map.visible_tiles[idx] {
+        if position.x == mouse_world.x && position.y == mouse_world.y && map.visible_tiles[idx] {
             tooltip.push(name.name.to_string());
         }
 
@@ -84,8 +101,8 @@ fn draw_tooltips(ecs: &World, ctx: &mut Rltk) {
             if mouse_pos.0 > 40 {
                 let arrow_pos = Point::new(mouse_pos.0 - 2, mouse_pos.1);
                 let left_x = mouse_pos.0 - width;
-                let mut y = mouse_pos.1;
-                for s in tooltip.iter() {
+                for (i, s) in tooltip.iter().enumerate() {
+                    let y = mouse_pos.1 + i as i32;
                     ctx.print_color(
                         left_x,
                         y,
@@ -100,23 +117,22 @@ fn draw_tooltips(ecs: &World, ctx: &mut Rltk) {
                             y,
                             RGB::named(rltk::WHITE),
                             RGB::named(rltk::GREY),
-                            &" ".to_string(),
+                            " ",
                         );
                     }
-                    y += 1;
                 }
                 ctx.print_color(
                     arrow_pos.x,
                     arrow_pos.y,
                     RGB::named(rltk::WHITE),
                     RGB::named(rltk::GREY),
-                    &"->".to_string(),
+                    "->",
                 );
             } else {
                 let arrow_pos = Point::new(mouse_pos.0 + 1, mouse_pos.1);
                 let left_x = mouse_pos.0 + 3;
-                let mut y = mouse_pos.1;
-                for s in tooltip.iter() {
+                for (i, s) in tooltip.iter().enumerate() {
+                    let y = mouse_pos.1 + i as i32;
                     ctx.print_color(
                         left_x + 1,
                         y,
@@ -131,17 +147,16 @@ fn draw_tooltips(ecs: &World, ctx: &mut Rltk) {
                             y,
                             RGB::named(rltk::WHITE),
                             RGB::named(rltk::GREY),
-                            &" ".to_string(),
+                            " ",
                         );
                     }
-                    y += 1;
                 }
                 ctx.print_color(
                     arrow_pos.x,
                     arrow_pos.y,
                     RGB::named(rltk::WHITE),
                     RGB::named(rltk::GREY),
-                    &"<-".to_string(),
+                    "<-",
                 );
             }
         }
@@ -155,22 +170,35 @@ pub enum ItemMenuResult {
     Selected,
 }
 
-pub fn show_inventory(gs: &mut State, ctx: &mut Rltk) -> (ItemMenuResult, Option<Entity>) {
-    let player_entity = gs.ecs.fetch::<Entity>();
-    let names = gs.ecs.read_storage::<Name>();
-    let backpack = gs.ecs.read_storage::<InBackpack>();
-    let entities = gs.ecs.entities();
+/// メニューの1行. letterは選ぶときの文字で, 0が'a'
+pub struct MenuOption {
+    pub letter: usize,
+    pub text: String,
+}
 
-    let inventory = (&backpack, &names)
-        .join()
-        .filter(|item| item.0.owner == *player_entity);
-    let count = inventory.count();
+impl MenuOption {
+    pub fn new(letter: usize, text: String) -> MenuOption {
+        MenuOption { letter, text }
+    }
+}
 
-    let mut y = (25 - (count / 2)) as i32;
+/// 文字で選ぶメニュー. 選ばれたらそのoptionの番号を返す
+/// 箱の大きさは中身に合わせる. 新しいメニューはこれを使って描く
+pub fn menu(
+    ctx: &mut Rltk,
+    keymap: &Keymap,
+    title: &str,
+    options: &[MenuOption],
+) -> (ItemMenuResult, Option<usize>) {
+    let count = options.len();
+    let widest = options.iter().map(|o| o.text.len()).max().unwrap_or(0);
+    let width = i32::max(31, i32::max(widest as i32 + 8, title.len() as i32 + 6));
+
+    let y = (25 - (count / 2)) as i32;
     ctx.draw_box(
         15,
         y - 2,
-        31,
+        width,
         (count + 3) as i32,
         RGB::named(rltk::WHITE),
         RGB::named(rltk::BLACK),
@@ -180,23 +208,18 @@ pub fn show_inventory(gs: &mut State, ctx: &mut Rltk) -> (ItemMenuResult, Option
         y - 2,
         RGB::named(rltk::YELLOW),
         RGB::named(rltk::BLACK),
-        "Inventory",
+        title,
     );
     ctx.print_color(
         18,
         y + count as i32 + 1,
         RGB::named(rltk::YELLOW),
         RGB::named(rltk::BLACK),
-        "ESCAPE to cancel",
+        format!("{} to cancel", keymap.key_label(Command::Cancel)),
     );
 
-    let mut equippable: Vec<Entity> = Vec::new();
-    // backpackに入っているitemを羅列する
-    let mut j = 0;
-    for (entity, _pack, name) in (&entities, &backpack, &names)
-        .join()
-        .filter(|item| item.1.owner == *player_entity)
-    {
+    for (j, option) in options.iter().enumerate() {
+        let y = y + j as i32;
         ctx.set(
             17,
             y,
@@ -209,7 +232,7 @@ pub fn show_inventory(gs: &mut State, ctx: &mut Rltk) -> (ItemMenuResult, Option
             y,
             RGB::named(rltk::YELLOW),
             RGB::named(rltk::BLACK),
-            97 + j as rltk::FontCharType, // 'a' + j
+            97 + option.letter as rltk::FontCharType, // 'a' + letter
         );
         ctx.set(
             19,
@@ -218,175 +241,1028 @@ pub fn show_inventory(gs: &mut State, ctx: &mut Rltk) -> (ItemMenuResult, Option
             RGB::named(rltk::BLACK),
             rltk::to_cp437(')'),
         );
-
-        ctx.print(21, y, &name.name.to_string());
-        equippable.push(entity);
-        y += 1;
-        j += 1;
+        ctx.print(21, y, &option.text);
     }
 
     match ctx.key {
         None => (ItemMenuResult::NoResponse, None),
-        Some(key) => match key {
-            VirtualKeyCode::Escape => (ItemMenuResult::Cancel, None),
+        Some(key) => match keymap.menu_command(key) {
+            Some(Command::Cancel) => (ItemMenuResult::Cancel, None),
             _ => {
                 let selection = rltk::letter_to_option(key);
-                if selection > -1 && selection < count as i32 {
-                    return (
-                        ItemMenuResult::Selected,
-                        Some(equippable[selection as usize]),
-                    );
+                match options.iter().position(|o| o.letter as i32 == selection) {
+                    Some(i) => (ItemMenuResult::Selected, Some(i)),
+                    None => (ItemMenuResult::NoResponse, None),
                 }
-                (ItemMenuResult::NoResponse, None)
             }
         },
     }
 }
 
-pub fn drop_item_menu(gs: &mut State, ctx: &mut Rltk) -> (ItemMenuResult, Option<Entity>) {
+/// ownerのbackpackの中身. (枠, entity)を枠の順に並べる
+fn backpack_items(ecs: &World, owner: Entity) -> Vec<(usize, Entity)> {
+    let entities = ecs.entities();
+    let backpack = ecs.read_storage::<InBackpack>();
+
+    let mut items: Vec<(usize, Entity)> = (&entities, &backpack)
+        .join()
+        .filter(|(_, pack)| pack.owner == owner)
+        .map(|(entity, pack)| (pack.slot, entity))
+        .collect();
+    items.sort_by_key(|(slot, _)| *slot);
+    items
+}
+
+/// 持ち物から1つ選ぶ. 文字は持ってる枠のもの
+fn choose_item(gs: &mut State, ctx: &mut Rltk, title: &str) -> (ItemMenuResult, Option<Entity>) {
+    let player_entity = *gs.ecs.fetch::<Entity>();
+    let keymap = gs.ecs.fetch::<Keymap>();
+
+    let inventory = backpack_items(&gs.ecs, player_entity);
+    let options: Vec<MenuOption> = inventory
+        .iter()
+        .map(|(slot, entity)| MenuOption::new(*slot, describe::item_name(&gs.ecs, *entity)))
+        .collect();
+
+    match menu(ctx, &keymap, title, &options) {
+        (ItemMenuResult::Selected, Some(i)) => (ItemMenuResult::Selected, Some(inventory[i].1)),
+        (result, _) => (result, None),
+    }
+}
+
+pub fn show_inventory(gs: &mut State, ctx: &mut Rltk) -> (ItemMenuResult, Option<Entity>) {
+    let player_entity = *gs.ecs.fetch::<Entity>();
+    let count = backpack_items(&gs.ecs, player_entity).len();
+    let capacity = gs.ecs.fetch::<Settings>().backpack_slots();
+    choose_item(gs, ctx, &format!("Inventory ({}/{})", count, capacity))
+}
+
+/// 足元にいくつもアイテムがあるときに, どれを拾うか選ぶ. 最後の行を選ぶとぜんぶ拾う
+/// 文字が足りなくて並べきれないアイテムも, ぜんぶ拾うときには拾う
+pub fn pickup_menu(gs: &mut State, ctx: &mut Rltk) -> (ItemMenuResult, Vec<Entity>) {
+    let player_pos = *gs.ecs.fetch::<Point>();
+    let keymap = gs.ecs.fetch::<Keymap>();
+
+    // 最後の1文字はぜんぶ拾う用にとっておく
+    let items = items_at(&gs.ecs, player_pos);
+    let shown = usize::min(items.len(), MAX_BACKPACK_SLOTS - 1);
+    let mut options: Vec<MenuOption> = items[..shown]
+        .iter()
+        .enumerate()
+        .map(|(i, item)| MenuOption::new(i, describe::item_name(&gs.ecs, *item)))
+        .collect();
+    let all = if shown < items.len() {
+        format!("Pick up all ({} more not shown)", items.len() - shown)
+    } else {
+        "Pick up all".to_string()
+    };
+    options.push(MenuOption::new(shown, all));
+
+    match menu(ctx, &keymap, "Pick Up Which Item?", &options) {
+        (ItemMenuResult::Selected, Some(i)) if i == shown => (ItemMenuResult::Selected, items),
+        (ItemMenuResult::Selected, Some(i)) => (ItemMenuResult::Selected, vec![items[i]]),
+        (result, _) => (result, Vec::new()),
+    }
+}
+
+/// 持ち物を選んだあとにできること
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum ItemAction {
+    Use,
+    Drop,
+    Throw,
+    Inspect,
+}
+
+impl ItemAction {
+    pub const ALL: [ItemAction; 4] = [
+        ItemAction::Use,
+        ItemAction::Drop,
+        ItemAction::Throw,
+        ItemAction::Inspect,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            ItemAction::Use => "Use",
+            ItemAction::Drop => "Drop",
+            ItemAction::Throw => "Throw",
+            ItemAction::Inspect => "Inspect",
+        }
+    }
+
+    // メニューで選ぶ文字. 増やすときはほかとかぶらない文字にする
+    fn key(self) -> char {
+        match self {
+            ItemAction::Use => 'u',
+            ItemAction::Drop => 'd',
+            ItemAction::Throw => 't',
+            ItemAction::Inspect => 'i',
+        }
+    }
+
+    fn letter(self) -> usize {
+        (self.key() as u8 - b'a') as usize
+    }
+}
+
+/// そのアイテムにできること. 使って効果のないアイテムは使えない
+fn item_actions(ecs: &World, item: Entity) -> Vec<ItemAction> {
+    let usable = ecs.read_storage::<ProvidesHealing>().get(item).is_some()
+        || ecs.read_storage::<InflictsDamage>().get(item).is_some()
+        || ecs.read_storage::<Confusion>().get(item).is_some();
+    ItemAction::ALL
+        .iter()
+        .copied()
+        .filter(|action| *action != ItemAction::Use || usable)
+        .collect()
+}
+
+/// 持ち物を選んだあとのメニュー
+pub fn item_action_menu(
+    gs: &mut State,
+    ctx: &mut Rltk,
+    item: Entity,
+) -> (ItemMenuResult, Option<ItemAction>) {
+    let keymap = gs.ecs.fetch::<Keymap>();
+    let actions = item_actions(&gs.ecs, item);
+    let options: Vec<MenuOption> = actions
+        .iter()
+        .map(|action| MenuOption::new(action.letter(), action.label().to_string()))
+        .collect();
+
+    let title = describe::item_name(&gs.ecs, item);
+    match menu(ctx, &keymap, &title, &options) {
+        (ItemMenuResult::Selected, Some(i)) => (ItemMenuResult::Selected, Some(actions[i])),
+        (result, _) => (result, None),
+    }
+}
+
+/// アイテムの説明を出す. 閉じるキーが押されたらCancelを返す
+pub fn inspect_item(gs: &mut State, ctx: &mut Rltk, item: Entity) -> ItemMenuResult {
+    let keymap = gs.ecs.fetch::<Keymap>();
+    let lines = describe::inspect_lines(&gs.ecs, item);
+
+    let count = lines.len();
+    let width = i32::max(
+        31,
+        lines.iter().map(|l| l.len()).max().unwrap_or(0) as i32 + 5,
+    );
+    let y = (25 - (count / 2)) as i32;
+    ctx.draw_box(
+        15,
+        y - 2,
+        width,
+        (count + 3) as i32,
+        RGB::named(rltk::WHITE),
+        RGB::named(rltk::BLACK),
+    );
+    ctx.print_color(
+        18,
+        y + count as i32 + 1,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        format!("{} to close", keymap.key_label(Command::Cancel)),
+    );
+    for (j, line) in lines.iter().enumerate() {
+        let fg = if j == 0 { rltk::YELLOW } else { rltk::WHITE };
+        ctx.print_color(
+            17,
+            y + j as i32,
+            RGB::named(fg),
+            RGB::named(rltk::BLACK),
+            line,
+        );
+    }
+
+    match ctx.key {
+        Some(key) if keymap.menu_command(key) == Some(Command::Cancel) => ItemMenuResult::Cancel,
+        _ => ItemMenuResult::NoResponse,
+    }
+}
+
+/// キャラクター画面の1行. 合計の値と, それが何から来てるかの内訳
+/// 装備みたいに値を変えるものが増えたら, stat_linesでpartsに内訳を足す
+pub struct StatLine {
+    label: &'static str,
+    parts: Vec<(&'static str, i32)>,
+}
+
+impl StatLine {
+    /// partsは(何から来てるか, 値). 最初のものが元の値
+    fn new(label: &'static str, parts: Vec<(&'static str, i32)>) -> StatLine {
+        StatLine { label, parts }
+    }
+
+    pub fn total(&self) -> i32 {
+        self.parts.iter().map(|(_, value)| value).sum()
+    }
+
+    /// "Power: 8 (base 5 + bonus 3)". 内訳がbaseだけなら合計だけ出す
+    fn text(&self) -> String {
+        if self.parts.len() == 1 {
+            return format!("{}: {}", self.label, self.total());
+        }
+        let mut breakdown = String::new();
+        for (i, (source, value)) in self.parts.iter().enumerate() {
+            if i == 0 {
+                breakdown += &format!("{} {}", source, value);
+            } else if *value < 0 {
+                breakdown += &format!(" - {} {}", source, -value);
+            } else {
+                breakdown += &format!(" + {} {}", source, value);
+            }
+        }
+        format!("{}: {} ({})", self.label, self.total(), breakdown)
+    }
+}
+
+// 今はまだ補正をかけるものがないので, どれも元の値だけ
+fn stat_lines(stats: &CombatStats) -> Vec<StatLine> {
+    vec![
+        StatLine::new("Max HP", vec![("base", stats.max_hp)]),
+        StatLine::new("Power", vec![("base", stats.power)]),
+        StatLine::new("Defense", vec![("base", stats.defense)]),
+    ]
+}
+
+/// キャラクター画面. 閉じるキーが押されたらCancelを返す
+pub fn show_character_sheet(gs: &mut State, ctx: &mut Rltk) -> ItemMenuResult {
     let player_entity = gs.ecs.fetch::<Entity>();
     let names = gs.ecs.read_storage::<Name>();
+    let combat_stats = gs.ecs.read_storage::<CombatStats>();
     let backpack = gs.ecs.read_storage::<InBackpack>();
-    let entities = gs.ecs.entities();
+    let statistics = gs.ecs.fetch::<Statistics>();
+    let keymap = gs.ecs.fetch::<Keymap>();
 
-    let inventory = (&backpack, &names)
+    let mut lines: Vec<String> = Vec::new();
+    if let Some(stats) = combat_stats.get(*player_entity) {
+        lines.push(format!("HP: {} / {}", stats.hp, stats.max_hp));
+        for line in stat_lines(stats).iter() {
+            lines.push(line.text());
+        }
+    }
+    lines.push(String::new());
+    let carried = backpack
         .join()
-        .filter(|item| item.0.owner == *player_entity);
-    let count = inventory.count();
+        .filter(|item| item.owner == *player_entity)
+        .count();
+    lines.push(format!("Items carried: {}", carried));
+    lines.push(format!("Turns: {}", statistics.turns));
+    lines.push(format!("Kills: {}", statistics.kills));
+    lines.push(format!("Damage dealt: {}", statistics.damage_dealt));
+    lines.push(format!("Damage taken: {}", statistics.damage_taken));
 
-    let mut y = (25 - (count / 2)) as i32;
+    let count = lines.len();
+    let y = (25 - (count / 2)) as i32;
     ctx.draw_box(
         15,
         y - 2,
-        31,
+        49,
         (count + 3) as i32,
         RGB::named(rltk::WHITE),
         RGB::named(rltk::BLACK),
     );
+    let name = names
+        .get(*player_entity)
+        .map_or("Character".to_string(), |n| n.name.clone());
     ctx.print_color(
         18,
         y - 2,
         RGB::named(rltk::YELLOW),
         RGB::named(rltk::BLACK),
-        "Drop Which Item?",
+        name,
     );
     ctx.print_color(
         18,
         y + count as i32 + 1,
         RGB::named(rltk::YELLOW),
         RGB::named(rltk::BLACK),
-        "ESCAPE to cancel",
+        format!("{} to close", keymap.key_label(Command::Cancel)),
     );
+    for (j, line) in lines.iter().enumerate() {
+        ctx.print(17, y + j as i32, line);
+    }
 
-    let mut equippable: Vec<Entity> = Vec::new();
-    let mut j = 0;
-    for (entity, _pack, name) in (&entities, &backpack, &names)
-        .join()
-        .filter(|item| item.1.owner == *player_entity)
-    {
-        ctx.set(
-            17,
-            y,
-            RGB::named(rltk::WHITE),
-            RGB::named(rltk::BLACK),
-            rltk::to_cp437('('),
-        );
-        ctx.set(
-            18,
-            y,
-            RGB::named(rltk::YELLOW),
-            RGB::named(rltk::BLACK),
-            97 + j as rltk::FontCharType,
-        );
-        ctx.set(
-            19,
-            y,
-            RGB::named(rltk::WHITE),
-            RGB::named(rltk::BLACK),
-            rltk::to_cp437(')'),
+    match ctx.key {
+        Some(key) if keymap.menu_command(key) == Some(Command::Cancel) => ItemMenuResult::Cancel,
+        _ => ItemMenuResult::NoResponse,
+    }
+}
+
+pub enum OptionsResult {
+    Cancel,
+    NoResponse,
+    Changed,
+    HighScores,
+}
+
+/// 設定を変えるメニュー. 選んだ項目を切り替える. 一番下から記録の画面も開ける
+/// 何か変えたらOptionsResult::Changedを返すので, 呼んだ側で反映と保存をする
+pub fn options_menu(gs: &mut State, ctx: &mut Rltk) -> OptionsResult {
+    let keymap = gs.ecs.fetch::<Keymap>();
+    let mut settings = gs.ecs.write_resource::<Settings>();
+
+    let on_off = |b: bool| if b { "On" } else { "Off" };
+    let font = if settings.font == gs.font_in_use {
+        settings.font.label().to_string()
+    } else {
+        // フォントは起動しなおさないと変わらない
+        format!("{} (after restart)", settings.font.label())
+    };
+    let mut options = vec![
+        format!("Scanlines: {}", on_off(settings.scanlines)),
+        format!("Font: {}", font),
+        format!("Message log length: {}", settings.log_length),
+        format!(
+            "Graphics: {}",
+            if settings.tiles { "Tiles" } else { "ASCII" }
+        ),
+        format!(
+            "Auto pickup: {} (hold {} to skip)",
+            on_off(settings.auto_pickup),
+            keymap.key_label(Command::StepOnly)
+        ),
+    ];
+    // 種類ごとの設定はauto pickupの下に並べる
+    for category in ItemCategory::ALL.iter() {
+        let enabled = settings.auto_pickup_categories.contains(category);
+        options.push(format!("  {}: {}", category.label(), on_off(enabled)));
+    }
+    options.push("High scores".to_string());
+    let high_scores = options.len() - 1;
+    let options: Vec<MenuOption> = options
+        .into_iter()
+        .enumerate()
+        .map(|(i, text)| MenuOption::new(i, text))
+        .collect();
+
+    match menu(ctx, &keymap, "Options", &options) {
+        (ItemMenuResult::Selected, Some(i)) if i == high_scores => OptionsResult::HighScores,
+        (ItemMenuResult::Selected, Some(i)) => {
+            match i {
+                0 => settings.toggle_scanlines(),
+                1 => settings.next_font(),
+                2 => settings.next_log_length(),
+                3 => settings.toggle_tiles(),
+                4 => settings.toggle_auto_pickup(),
+                i => settings.toggle_auto_pickup_category(ItemCategory::ALL[i - 5]),
+            }
+            OptionsResult::Changed
+        }
+        (ItemMenuResult::Cancel, _) => OptionsResult::Cancel,
+        _ => OptionsResult::NoResponse,
+    }
+}
+
+/// 記録の表を描く. いま倒れたときの記録は黄色にして, 10位より下でも最後に出す
+fn draw_score_table(ctx: &mut Rltk, y: i32, scores: &HighScores) {
+    let latest = scores
+        .latest
+        .as_ref()
+        .and_then(|latest| scores.entries.iter().position(|e| e == latest));
+    let mut rows: Vec<usize> = (0..scores.entries.len().min(SHOWN_SCORES)).collect();
+    if let Some(rank) = latest {
+        if rank >= SHOWN_SCORES {
+            rows.push(rank);
+        }
+    }
+
+    ctx.draw_box(
+        1,
+        y,
+        77,
+        i32::max(rows.len() as i32, 1) + 1,
+        RGB::named(rltk::WHITE),
+        RGB::named(rltk::BLACK),
+    );
+    ctx.print_color(
+        3,
+        y,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "High Scores",
+    );
+    if rows.is_empty() {
+        ctx.print(3, y + 1, "No scores yet");
+    }
+    for (i, rank) in rows.iter().enumerate() {
+        let entry = &scores.entries[*rank];
+        let line = format!(
+            "{:>3}. {:>5}  {:<10} {:>5}T {:>3}K {:>3}I  {}  {}",
+            rank + 1,
+            entry.score(),
+            entry.name,
+            entry.turns,
+            entry.kills,
+            entry.items,
+            entry.date,
+            entry.cause
         );
+        let line: String = line.chars().take(74).collect();
+        let fg = if Some(*rank) == latest {
+            RGB::named(rltk::YELLOW)
+        } else {
+            RGB::named(rltk::WHITE)
+        };
+        ctx.print_color(3, y + 1 + i as i32, fg, RGB::named(rltk::BLACK), line);
+    }
+}
+
+/// オプションから開く記録の画面
+pub fn high_scores(gs: &mut State, ctx: &mut Rltk) -> ItemMenuResult {
+    let keymap = gs.ecs.fetch::<Keymap>();
+    draw_score_table(ctx, 10, &gs.ecs.fetch::<HighScores>());
+    ctx.print_color(
+        3,
+        10 + SHOWN_SCORES as i32 + 3,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        format!("{} to close", keymap.key_label(Command::Cancel)),
+    );
 
-        ctx.print(21, y, &name.name.to_string());
-        equippable.push(entity);
-        y += 1;
-        j += 1;
+    match ctx.key {
+        Some(key) if keymap.menu_command(key) == Some(Command::Cancel) => ItemMenuResult::Cancel,
+        _ => ItemMenuResult::NoResponse,
+    }
+}
+
+/// playerが倒れたあとの画面. 死因と記録の表を出して, Cancelのキーで終わる
+pub fn game_over(gs: &mut State, ctx: &mut Rltk) -> ItemMenuResult {
+    let keymap = gs.ecs.fetch::<Keymap>();
+    let statistics = gs.ecs.fetch::<Statistics>();
+    let cause = statistics
+        .cause_of_death
+        .clone()
+        .unwrap_or_else(|| "Cause of death unknown".to_string());
+    let lines = [
+        cause,
+        format!("Survived {} turns", statistics.turns),
+        format!("Monsters killed: {}", statistics.kills),
+        format!("Items found: {}", statistics.items_found),
+    ];
+
+    let width = lines.iter().map(|l| l.len()).max().unwrap_or(0) as i32 + 4;
+    let width = i32::max(width, 31);
+    let x = 40 - width / 2;
+    let y = 3;
+    ctx.draw_box(
+        x,
+        y,
+        width,
+        lines.len() as i32 + 3,
+        RGB::named(rltk::WHITE),
+        RGB::named(rltk::BLACK),
+    );
+    ctx.print_color(
+        x + 2,
+        y,
+        RGB::named(rltk::RED),
+        RGB::named(rltk::BLACK),
+        "You are dead",
+    );
+    for (i, line) in lines.iter().enumerate() {
+        ctx.print(x + 2, y + 2 + i as i32, line);
     }
+    ctx.print_color(
+        x + 2,
+        y + lines.len() as i32 + 3,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        format!("{} to quit", keymap.key_label(Command::Cancel)),
+    );
+
+    draw_score_table(ctx, 12, &gs.ecs.fetch::<HighScores>());
 
     match ctx.key {
-        None => (ItemMenuResult::NoResponse, None),
-        Some(key) => match key {
-            VirtualKeyCode::Escape => (ItemMenuResult::Cancel, None),
-            _ => {
-                let selection = rltk::letter_to_option(key);
-                if selection > -1 && selection < count as i32 {
-                    return (
-                        ItemMenuResult::Selected,
-                        Some(equippable[selection as usize]),
-                    );
-                }
-                (ItemMenuResult::NoResponse, None)
+        Some(key) if keymap.menu_command(key) == Some(Command::Cancel) => ItemMenuResult::Cancel,
+        _ => ItemMenuResult::NoResponse,
+    }
+}
+
+pub enum ConsoleResult {
+    Cancel,
+    NoResponse,
+    MoveCursor(Point),
+    Submit,
+}
+
+/// コンソールに打てる文字. 名前に使われてる記号だけ
+fn key_char(key: rltk::VirtualKeyCode, shift: bool) -> Option<char> {
+    use rltk::VirtualKeyCode::*;
+    let c = match key {
+        A => 'a',
+        B => 'b',
+        C => 'c',
+        D => 'd',
+        E => 'e',
+        F => 'f',
+        G => 'g',
+        H => 'h',
+        I => 'i',
+        J => 'j',
+        K => 'k',
+        L => 'l',
+        M => 'm',
+        N => 'n',
+        O => 'o',
+        P => 'p',
+        Q => 'q',
+        R => 'r',
+        S => 's',
+        T => 't',
+        U => 'u',
+        V => 'v',
+        W => 'w',
+        X => 'x',
+        Y => 'y',
+        Z => 'z',
+        Key0 | Numpad0 => '0',
+        Key1 | Numpad1 => '1',
+        Key2 | Numpad2 => '2',
+        Key3 | Numpad3 => '3',
+        Key4 | Numpad4 => '4',
+        Key5 | Numpad5 => '5',
+        Key6 | Numpad6 => '6',
+        Key7 | Numpad7 => '7',
+        Key8 | Numpad8 => '8',
+        Key9 | Numpad9 => '9',
+        Space => ' ',
+        Minus | NumpadSubtract => '-',
+        Apostrophe => '\'',
+        _ => return None,
+    };
+    Some(if shift { c.to_ascii_uppercase() } else { c })
+}
+
+/// wizardのコンソール. 文字を打ってEnterで実行する
+/// spawnやteleportの場所はカーソルのマスで, カーソルのキーかクリックで動かす
+pub fn wizard_console(gs: &mut State, ctx: &mut Rltk, cursor: Point) -> ConsoleResult {
+    let keymap = gs.ecs.fetch::<Keymap>();
+    let mut input = gs.ecs.write_resource::<ConsoleInput>();
+
+    if let Some(screen) = camera::world_to_screen(&gs.ecs, cursor) {
+        highlight(ctx, screen.x, screen.y, RGB::named(rltk::MAGENTA));
+    }
+    ctx.draw_box(
+        0,
+        0,
+        camera::VIEW_WIDTH - 1,
+        3,
+        RGB::named(rltk::MAGENTA),
+        RGB::named(rltk::BLACK),
+    );
+    ctx.print_color(
+        2,
+        0,
+        RGB::named(rltk::MAGENTA),
+        RGB::named(rltk::BLACK),
+        format!("Wizard console: cursor at ({}, {})", cursor.x, cursor.y),
+    );
+    ctx.print(2, 1, format!("> {}_", input.text));
+    ctx.print_color(
+        2,
+        2,
+        RGB::named(rltk::GREY),
+        RGB::named(rltk::BLACK),
+        format!(
+            "{}/{}/{}/{}: cursor, {}: erase, {}: run, {}: close",
+            keymap.key_label(Command::CursorLeft),
+            keymap.key_label(Command::CursorRight),
+            keymap.key_label(Command::CursorUp),
+            keymap.key_label(Command::CursorDown),
+            keymap.key_label(Command::Erase),
+            keymap.key_label(Command::Confirm),
+            keymap.key_label(Command::Cancel)
+        ),
+    );
+
+    if ctx.left_click {
+        let (mx, my) = ctx.mouse_pos();
+        if let Some(clicked) = camera::screen_to_world(&gs.ecs, Point::new(mx, my)) {
+            return ConsoleResult::MoveCursor(clicked);
+        }
+    }
+
+    let key = match ctx.key {
+        None => return ConsoleResult::NoResponse,
+        Some(key) => key,
+    };
+    let delta = match keymap.menu_command(key) {
+        Some(Command::Cancel) => return ConsoleResult::Cancel,
+        Some(Command::Confirm) => return ConsoleResult::Submit,
+        Some(Command::Erase) => {
+            input.text.pop();
+            return ConsoleResult::NoResponse;
+        }
+        Some(Command::CursorLeft) => Point::new(-1, 0),
+        Some(Command::CursorRight) => Point::new(1, 0),
+        Some(Command::CursorUp) => Point::new(0, -1),
+        Some(Command::CursorDown) => Point::new(0, 1),
+        _ => {
+            if let Some(c) = key_char(key, ctx.shift) {
+                input.text.push(c);
             }
-        },
+            return ConsoleResult::NoResponse;
+        }
+    };
+    match move_cursor(&gs.ecs, cursor, delta) {
+        Some(moved) => ConsoleResult::MoveCursor(moved),
+        None => ConsoleResult::NoResponse,
     }
 }
 
+pub enum TargetResult {
+    Cancel,
+    NoResponse,
+    // カーソルかマウスが動いた. 次のフレームはここから
+    Moved { cursor: Point, mouse: Point },
+    Selected(Point),
+}
+
+/// 射程内で見えてるマス
+fn targetable_cells(ecs: &World, range: i32) -> Option<Vec<Point>> {
+    let player_entity = ecs.fetch::<Entity>();
+    let player_pos = ecs.fetch::<Point>();
+    let viewsheds = ecs.read_storage::<Viewshed>();
+
+    let visible = viewsheds.get(*player_entity)?;
+    Some(
+        visible
+            .visible_tiles
+            .iter()
+            .filter(|p| rltk::DistanceAlg::Pythagoras.distance2d(*player_pos, **p) <= range as f32)
+            .copied()
+            .collect(),
+    )
+}
+
+/// 狙えるマスにいるmonster. 近い順
+fn hostile_targets(ecs: &World, cells: &[Point]) -> Vec<Point> {
+    let player_pos = ecs.fetch::<Point>();
+    let positions = ecs.read_storage::<Position>();
+    let monsters = ecs.read_storage::<Monster>();
+
+    let mut targets: Vec<Point> = (&positions, &monsters)
+        .join()
+        .map(|(pos, _)| Point::new(pos.x, pos.y))
+        .filter(|p| cells.contains(p))
+        .collect();
+    targets.sort_by(|a, b| {
+        let da = rltk::DistanceAlg::Pythagoras.distance2d(*player_pos, *a);
+        let db = rltk::DistanceAlg::Pythagoras.distance2d(*player_pos, *b);
+        da.total_cmp(&db)
+    });
+    targets.dedup();
+    targets
+}
+
+/// 狙い始めるときのカーソル. 一番近いmonster, いなければplayerの足元
+pub fn initial_target(ecs: &World, range: i32) -> Point {
+    let cells = targetable_cells(ecs, range).unwrap_or_default();
+    match hostile_targets(ecs, &cells).first() {
+        Some(p) => *p,
+        None => *ecs.fetch::<Point>(),
+    }
+}
+
+/// 移動キーをカーソルの動きにする
+fn cursor_delta(keymap: &Keymap, key: rltk::VirtualKeyCode) -> Option<Point> {
+    match keymap.game_command(key)? {
+        Command::MoveLeft => Some(Point::new(-1, 0)),
+        Command::MoveRight => Some(Point::new(1, 0)),
+        Command::MoveUp => Some(Point::new(0, -1)),
+        Command::MoveDown => Some(Point::new(0, 1)),
+        Command::MoveUpLeft => Some(Point::new(-1, -1)),
+        Command::MoveUpRight => Some(Point::new(1, -1)),
+        Command::MoveDownLeft => Some(Point::new(-1, 1)),
+        Command::MoveDownRight => Some(Point::new(1, 1)),
+        _ => None,
+    }
+}
+
+/// 画面に映ってるマップの中ならカーソルを動かせる
+fn move_cursor(ecs: &World, cursor: Point, delta: Point) -> Option<Point> {
+    let moved = cursor + delta;
+    let screen = camera::world_to_screen(ecs, moved)?;
+    camera::screen_to_world(ecs, screen)
+}
+
+/// 遠くを狙うアイテムのターゲットを選ぶ. 投げるときもこれで狙う
+/// キーボードでカーソルを動かすか, Tabでmonsterを順番に選んで決定キーで撃つ
+/// マウスが動いたらカーソルはマウスについていく. クリックでもそのマスを撃てる
 pub fn ranged_target(
     gs: &mut State,
     ctx: &mut Rltk,
     range: i32,
-) -> (ItemMenuResult, Option<Point>) {
-    let player_entity = gs.ecs.fetch::<Entity>();
-    let player_pos = gs.ecs.fetch::<Point>();
-    let viewsheds = gs.ecs.read_storage::<Viewshed>();
+    item: Entity,
+    action: ItemAction,
+    cursor: Point,
+    last_mouse: Point,
+) -> TargetResult {
+    let keymap = gs.ecs.fetch::<Keymap>();
+    let throwing = action == ItemAction::Throw;
 
     ctx.print_color(
         5,
         0,
         RGB::named(rltk::YELLOW),
         RGB::named(rltk::BLACK),
-        "Select Target:",
+        format!(
+            "{}: {} to cycle, {} to fire, {} to cancel",
+            if throwing {
+                "Throw At"
+            } else {
+                "Select Target"
+            },
+            keymap.key_label(Command::NextTarget),
+            keymap.key_label(Command::Confirm),
+            keymap.key_label(Command::Cancel)
+        ),
     );
 
-    // 選択可能なセルをハイライトする
-    // 視界内 かつ itemのrange内を満たすセル
-    let mut available_cells = Vec::new();
-    let visible = viewsheds.get(*player_entity);
-
-    if let Some(visible) = visible {
-        // 視界内からターゲット可能なセルを選ぶ
-        for idx in visible.visible_tiles.iter() {
-            let distance = rltk::DistanceAlg::Pythagoras.distance2d(*player_pos, *idx);
-            if distance <= range as f32 {
-                ctx.set_bg(idx.x, idx.y, RGB::named(rltk::BLUE));
-                available_cells.push(idx);
+    // 選択可能なセル. 視界内 かつ itemのrange内を満たすセル
+    let available_cells = match targetable_cells(&gs.ecs, range) {
+        Some(cells) => cells,
+        None => return TargetResult::Cancel,
+    };
+
+    let mouse = Point::new(ctx.mouse_pos().0, ctx.mouse_pos().1);
+    let mouse_world = camera::screen_to_world(&gs.ecs, mouse);
+    let mut new_cursor = cursor;
+    if mouse != last_mouse {
+        if let Some(world) = mouse_world {
+            new_cursor = world;
+        }
+    }
+
+    if let Some(key) = ctx.key {
+        match keymap.menu_command(key) {
+            Some(Command::Cancel) => return TargetResult::Cancel,
+            Some(Command::Confirm) if available_cells.contains(&new_cursor) => {
+                return TargetResult::Selected(new_cursor);
+            }
+            Some(Command::NextTarget) => {
+                let targets = hostile_targets(&gs.ecs, &available_cells);
+                let next = match targets.iter().position(|p| *p == new_cursor) {
+                    Some(i) => targets.get((i + 1) % targets.len()),
+                    None => targets.first(),
+                };
+                if let Some(next) = next {
+                    new_cursor = *next;
+                }
+            }
+            _ => {
+                if let Some(delta) = cursor_delta(&keymap, key) {
+                    if let Some(moved) = move_cursor(&gs.ecs, new_cursor, delta) {
+                        new_cursor = moved;
+                    }
+                }
             }
         }
-    } else {
-        return (ItemMenuResult::Cancel, None);
     }
 
-    // マウスカーソルを描画する
-    let mouse_pos = ctx.mouse_pos();
-    let mut valid_target = false;
-    for idx in available_cells.iter() {
-        if idx.x == mouse_pos.0 && idx.y == mouse_pos.1 {
-            valid_target = true;
+    if ctx.left_click {
+        // 範囲外をクリックしたらキャンセル
+        return match mouse_world {
+            Some(world) if available_cells.contains(&world) => TargetResult::Selected(world),
+            _ => TargetResult::Cancel,
+        };
+    }
+
+    for cell in available_cells.iter() {
+        if let Some(screen) = camera::world_to_screen(&gs.ecs, *cell) {
+            highlight(ctx, screen.x, screen.y, RGB::named(rltk::BLUE));
         }
     }
-    if valid_target {
-        ctx.set_bg(mouse_pos.0, mouse_pos.1, RGB::named(rltk::CYAN));
-        if ctx.left_click {
-            return (
-                ItemMenuResult::Selected,
-                Some(Point::new(mouse_pos.0, mouse_pos.1)),
+
+    let valid_target = available_cells.contains(&new_cursor);
+    let player_pos = *gs.ecs.fetch::<Point>();
+    let mut impact = new_cursor;
+
+    // boltや投げたものなら飛んでいく道すじと, 実際に止まるマスを見せる
+    let flies = throwing || gs.ecs.read_storage::<Bolt>().get(item).is_some();
+    if valid_target && flies {
+        let path = bolt_path(&gs.ecs.fetch::<Map>(), player_pos, new_cursor);
+        impact = *path.last().unwrap_or(&new_cursor);
+        for point in path.iter() {
+            if let Some(screen) = camera::world_to_screen(&gs.ecs, *point) {
+                highlight(ctx, screen.x, screen.y, RGB::named(rltk::YELLOW));
+            }
+        }
+        if let Some(screen) = camera::world_to_screen(&gs.ecs, impact) {
+            ctx.set(
+                screen.x,
+                screen.y,
+                RGB::named(rltk::RED),
+                RGBA::from_f32(1.0, 0.0, 0.0, 0.6),
+                rltk::to_cp437('*'),
             );
         }
+    }
+
+    // 範囲攻撃なら爆発するマスを全部見せる. 自分が巻きこまれるなら警告する
+    // 投げたときは爆発しない
+    if valid_target && !throwing {
+        if let Some(aoe) = gs.ecs.read_storage::<AreaOfEffect>().get(item) {
+            let blast = blast_tiles(&gs.ecs.fetch::<Map>(), impact, aoe.radius);
+            for tile in blast.iter() {
+                if let Some(screen) = camera::world_to_screen(&gs.ecs, *tile) {
+                    highlight(ctx, screen.x, screen.y, RGB::named(rltk::ORANGE));
+                }
+            }
+            if blast.contains(&player_pos) {
+                ctx.print_color(
+                    5,
+                    1,
+                    RGB::named(rltk::RED),
+                    RGB::named(rltk::BLACK),
+                    "Warning: you are inside the blast!",
+                );
+            }
+        }
+    }
+
+    // カーソルを描画する
+    if let Some(screen) = camera::world_to_screen(&gs.ecs, new_cursor) {
+        let colour = if valid_target { rltk::CYAN } else { rltk::RED };
+        highlight(ctx, screen.x, screen.y, RGB::named(colour));
+    }
+
+    if new_cursor != cursor || mouse != last_mouse {
+        return TargetResult::Moved {
+            cursor: new_cursor,
+            mouse,
+        };
+    }
+    TargetResult::NoResponse
+}
+
+pub enum LookResult {
+    Cancel,
+    NoResponse,
+    MoveCursor(Point),
+}
+
+/// look modeで見られるentity. 視界内のmonsterと落ちてるアイテムを近い順に並べる
+fn look_targets(ecs: &World) -> Vec<Point> {
+    let player_pos = ecs.fetch::<Point>();
+    let map = ecs.fetch::<Map>();
+    let positions = ecs.read_storage::<Position>();
+    let monsters = ecs.read_storage::<Monster>();
+    let items = ecs.read_storage::<Item>();
+
+    let mut targets: Vec<Point> = (&positions, monsters.maybe(), items.maybe())
+        .join()
+        .filter(|(_, monster, item)| monster.is_some() || item.is_some())
+        .map(|(pos, _, _)| Point::new(pos.x, pos.y))
+        .filter(|p| map.visible_tiles[map.xy_idx(p.x, p.y)])
+        .collect();
+    targets.sort_by(|a, b| {
+        let da = rltk::DistanceAlg::Pythagoras.distance2d(*player_pos, *a);
+        let db = rltk::DistanceAlg::Pythagoras.distance2d(*player_pos, *b);
+        da.total_cmp(&db)
+    });
+    targets.dedup();
+    targets
+}
+
+/// カーソルのマスの説明. 一番上に見えてるentityを詳しく, ほかは名前だけ
+fn look_description(ecs: &World, cursor: Point) -> Vec<String> {
+    let map = ecs.fetch::<Map>();
+    let idx = map.xy_idx(cursor.x, cursor.y);
+    if !map.revealed_tiles[idx] {
+        return vec!["Unknown".to_string()];
+    }
+    if !map.visible_tiles[idx] {
+        return vec![
+            tile_name(map.tiles[idx]).to_string(),
+            "You can't see it from here".to_string(),
+        ];
+    }
+
+    let entities = ecs.entities();
+    let positions = ecs.read_storage::<Position>();
+    let renderables = ecs.read_storage::<Renderable>();
+    let names = ecs.read_storage::<Name>();
+    let mut here: Vec<(Entity, i32)> = (&entities, &positions, &renderables)
+        .join()
+        .filter(|(_, pos, _)| pos.x == cursor.x && pos.y == cursor.y)
+        .map(|(entity, _, render)| (entity, render.render_order))
+        .collect();
+    // render_orderが小さいほど上に描かれる
+    here.sort_by_key(|(_, order)| *order);
+
+    let mut lines = Vec::new();
+    if let Some((top, _)) = here.first() {
+        lines.extend(describe::describe_entity(ecs, *top));
+        let others: Vec<String> = here[1..]
+            .iter()
+            .filter_map(|(entity, _)| names.get(*entity).map(|n| n.name.clone()))
+            .collect();
+        if !others.is_empty() {
+            lines.push(format!("Also here: {}", others.join(", ")));
+        }
+        lines.push(format!("On {}", tile_name(map.tiles[idx]).to_lowercase()));
     } else {
-        ctx.set_bg(mouse_pos.0, mouse_pos.1, RGB::named(rltk::RED));
-        if ctx.left_click {
-            return (ItemMenuResult::Cancel, None);
+        lines.push(tile_name(map.tiles[idx]).to_string());
+    }
+    lines
+}
+
+/// キーボードでカーソルを動かしてマスを調べる
+/// 移動キーでカーソルを動かして, Tabで見えてるmonsterとアイテムを順番に選ぶ
+pub fn look_mode(gs: &mut State, ctx: &mut Rltk, cursor: Point) -> LookResult {
+    let keymap = gs.ecs.fetch::<Keymap>();
+
+    ctx.print_color(
+        5,
+        0,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        format!(
+            "Look: move the cursor, {} to cycle, {} to stop",
+            keymap.key_label(Command::NextTarget),
+            keymap.key_label(Command::Cancel)
+        ),
+    );
+
+    let screen = camera::world_to_screen(&gs.ecs, cursor);
+    if let Some(screen) = screen {
+        highlight(ctx, screen.x, screen.y, RGB::named(rltk::CYAN));
+    }
+
+    // カーソルと重ならないように, 画面の反対側に説明を出す
+    let lines = look_description(&gs.ecs, cursor);
+    let width = lines.iter().map(|l| l.len()).max().unwrap_or(0) as i32 + 3;
+    let x = match screen {
+        Some(screen) if screen.x < camera::VIEW_WIDTH / 2 => camera::VIEW_WIDTH - width - 2,
+        _ => 1,
+    };
+    ctx.draw_box(
+        x,
+        2,
+        width,
+        lines.len() as i32 + 1,
+        RGB::named(rltk::WHITE),
+        RGB::named(rltk::BLACK),
+    );
+    for (j, line) in lines.iter().enumerate() {
+        let fg = if j == 0 { rltk::YELLOW } else { rltk::WHITE };
+        ctx.print_color(
+            x + 2,
+            3 + j as i32,
+            RGB::named(fg),
+            RGB::named(rltk::BLACK),
+            line,
+        );
+    }
+
+    let key = match ctx.key {
+        None => return LookResult::NoResponse,
+        Some(key) => key,
+    };
+    match keymap.menu_command(key) {
+        Some(Command::Cancel) => return LookResult::Cancel,
+        Some(Command::NextTarget) => {
+            let targets = look_targets(&gs.ecs);
+            let next = match targets.iter().position(|p| *p == cursor) {
+                Some(i) => targets.get((i + 1) % targets.len()),
+                None => targets.first(),
+            };
+            return match next {
+                Some(p) => LookResult::MoveCursor(*p),
+                None => LookResult::NoResponse,
+            };
         }
+        _ => {}
     }
 
-    (ItemMenuResult::NoResponse, None)
+    match cursor_delta(&keymap, key).and_then(|delta| move_cursor(&gs.ecs, cursor, delta)) {
+        Some(moved) => LookResult::MoveCursor(moved),
+        None => LookResult::NoResponse,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stat_line_shows_breakdown() {
+        assert_eq!(StatLine::new("Power", vec![("base", 5)]).text(), "Power: 5");
+        let line = StatLine::new("Power", vec![("base", 5), ("bonus", 3), ("curse", -1)]);
+        assert_eq!(line.total(), 7);
+        assert_eq!(line.text(), "Power: 7 (base 5 + bonus 3 - curse 1)");
+    }
+
+    #[test]
+    fn item_actions_have_their_own_letters() {
+        for (i, a) in ItemAction::ALL.iter().enumerate() {
+            assert!(a.key().is_ascii_lowercase(), "{:?}", a);
+            for b in ItemAction::ALL.iter().skip(i + 1) {
+                assert_ne!(a.letter(), b.letter(), "{:?} and {:?}", a, b);
+            }
+        }
+    }
 }
@@ -0,0 +1,210 @@
+use super::{config, morgue, statistics::Statistics, Name};
+use specs::prelude::*;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+
+// 1行に1回分の記録をタブ区切りで書く. 新しい記録は最後に足すだけで, 書き直さない
+// 2つのゲームが同時に書いても行が混ざらないように, 書くあいだはファイルをロックする
+// 途中で止まって壊れた行は読むときに飛ばす
+const HIGH_SCORE_FILE: &str = "highscores.tsv";
+const FIELDS: usize = 6;
+
+// 画面に出す記録の数
+pub const SHOWN_SCORES: usize = 10;
+
+const KILL_POINTS: i32 = 10;
+const ITEM_POINTS: i32 = 5;
+
+#[derive(Debug)]
+pub enum HighScoreError {
+    Io(String),
+    NoDataDir,
+}
+
+impl fmt::Display for HighScoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HighScoreError::Io(e) => write!(f, "Unable to access {}: {}", HIGH_SCORE_FILE, e),
+            HighScoreError::NoDataDir => write!(f, "No data directory for {}", HIGH_SCORE_FILE),
+        }
+    }
+}
+
+impl std::error::Error for HighScoreError {}
+
+/// 1回分の記録
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct ScoreEntry {
+    pub date: String,
+    pub name: String,
+    pub turns: i32,
+    pub kills: i32,
+    pub items: i32,
+    pub cause: String,
+}
+
+impl ScoreEntry {
+    /// 倒れたplayerの記録
+    pub fn from_world(ecs: &World) -> ScoreEntry {
+        let player_entity = ecs.fetch::<Entity>();
+        let statistics = ecs.fetch::<Statistics>();
+        ScoreEntry {
+            date: morgue::today(),
+            name: ecs
+                .read_storage::<Name>()
+                .get(*player_entity)
+                .map_or("???".to_string(), |n| n.name.clone()),
+            turns: statistics.turns,
+            kills: statistics.kills,
+            items: statistics.items_found,
+            cause: statistics
+                .cause_of_death
+                .clone()
+                .unwrap_or_else(|| "Cause of death unknown".to_string()),
+        }
+    }
+
+    /// 生きたターン数に, 倒した数と見つけたアイテムの数のぶんを足す
+    pub fn score(&self) -> i32 {
+        self.turns + self.kills * KILL_POINTS + self.items * ITEM_POINTS
+    }
+
+    fn to_line(&self) -> String {
+        // タブと改行は区切りに使ってるので, 名前や死因に入ってたら空白にする
+        let clean = |s: &str| s.replace(['\t', '\n', '\r'], " ");
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\n",
+            clean(&self.date),
+            clean(&self.name),
+            self.turns,
+            self.kills,
+            self.items,
+            clean(&self.cause)
+        )
+    }
+
+    fn from_line(line: &str) -> Option<ScoreEntry> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != FIELDS {
+            return None;
+        }
+        Some(ScoreEntry {
+            date: fields[0].to_string(),
+            name: fields[1].to_string(),
+            turns: fields[2].parse().ok()?,
+            kills: fields[3].parse().ok()?,
+            items: fields[4].parse().ok()?,
+            cause: fields[5].to_string(),
+        })
+    }
+}
+
+/// 画面に出すために読んでおいた記録. latestはいま倒れたときの記録
+#[derive(Default)]
+pub struct HighScores {
+    pub entries: Vec<ScoreEntry>,
+    pub latest: Option<ScoreEntry>,
+}
+
+/// ファイルの中身を点数の高い順に並べる. 読めない行は飛ばす
+fn parse(text: &str) -> Vec<ScoreEntry> {
+    let mut entries: Vec<ScoreEntry> = text.lines().filter_map(ScoreEntry::from_line).collect();
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.score()));
+    entries
+}
+
+/// 記録を読む. ファイルがまだなければ空
+pub fn load() -> Result<Vec<ScoreEntry>, HighScoreError> {
+    let path = config::data_file(HIGH_SCORE_FILE).ok_or(HighScoreError::NoDataDir)?;
+    match fs::read_to_string(&path) {
+        Ok(text) => Ok(parse(&text)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(HighScoreError::Io(e.to_string())),
+    }
+}
+
+/// 記録をファイルの最後に足す
+pub fn record(entry: &ScoreEntry) -> Result<(), HighScoreError> {
+    let path = config::data_file(HIGH_SCORE_FILE).ok_or(HighScoreError::NoDataDir)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| HighScoreError::Io(e.to_string()))?;
+    }
+    append(&path, entry)
+}
+
+/// 1行ぶんをまとめてつくってから, ロックを取って書く
+/// ロックは他のゲームのappendとだけ取り合う. ファイルを閉じたら外れる
+fn append(path: &Path, entry: &ScoreEntry) -> Result<(), HighScoreError> {
+    let line = entry.to_line();
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| HighScoreError::Io(e.to_string()))?;
+    file.lock().map_err(|e| HighScoreError::Io(e.to_string()))?;
+    file.write_all(line.as_bytes())
+        .map_err(|e| HighScoreError::Io(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, turns: i32) -> ScoreEntry {
+        ScoreEntry {
+            date: "2026-10-18".to_string(),
+            name: name.to_string(),
+            turns,
+            kills: 2,
+            items: 1,
+            cause: "Killed by\tOrc".to_string(),
+        }
+    }
+
+    #[test]
+    fn concurrent_appends_keep_every_line() {
+        let path = std::env::temp_dir().join(format!("highscores-test-{}.tsv", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let writers: Vec<_> = ["Pon", "Tanu"]
+            .iter()
+            .map(|name| {
+                let path = path.clone();
+                let entry = entry(&name.repeat(200), 1);
+                std::thread::spawn(move || {
+                    for _ in 0..200 {
+                        append(&path, &entry).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let entries = parse(&fs::read_to_string(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+        assert_eq!(entries.len(), 400);
+        assert_eq!(
+            entries.iter().filter(|e| e.name.starts_with("Pon")).count(),
+            200
+        );
+    }
+
+    #[test]
+    fn broken_lines_are_skipped() {
+        let text = format!(
+            "{}{}Pon\t12\n{}",
+            entry("Pon", 100).to_line(),
+            "garbage\n",
+            entry("Pon", 300).to_line()
+        );
+        let entries = parse(&text);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].turns, 300);
+        assert_eq!(entries[1].score(), 100 + 2 * KILL_POINTS + ITEM_POINTS);
+        assert_eq!(entries[1].cause, "Killed by Orc");
+    }
+}
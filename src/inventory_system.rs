@@ -1,43 +1,218 @@
 use super::{
-    gamelog::GameLog, AreaOfEffect, CombatStats, Confusion, Consumable, InBackpack, InflictsDamage,
-    Map, Name, Position, ProvidesHealing, SufferDamage, WantsToDropItem, WantsToPickupItem,
-    WantsToUseItem,
+    gamelog::GameLog, settings::Settings, spawner, statistics::Statistics, AreaOfEffect, Bolt,
+    CombatStats, Confusion, Consumable, DroppedByPlayer, InBackpack, InflictsDamage, Item, Map,
+    Name, Position, ProvidesHealing, Ranged, Stackable, SufferDamage, WantsToDropItem,
+    WantsToPickupItem, WantsToThrowItem, WantsToUseItem,
 };
+use rltk::Point;
+use serde::{Deserialize, Serialize};
 use specs::prelude::*;
 
+/// fromからtoへ飛んでいくboltが通るマス. 最後のマスがboltの止まるところ
+/// 壁, 閉じたドア, monsterみたいに道をふさぐものがあればそこで止まる
+/// fromのマスは含まない. 自分の足元を狙ったときは足元で止まる
+pub fn bolt_path(map: &Map, from: Point, to: Point) -> Vec<Point> {
+    if from == to {
+        return vec![to];
+    }
+    let mut path = Vec::new();
+    for point in rltk::line2d(rltk::LineAlg::Bresenham, from, to) {
+        if point == from {
+            continue;
+        }
+        path.push(point);
+        let idx = map.xy_idx(point.x, point.y);
+        // blockedには深い水みたいな歩けないだけの地形も入ってるので,
+        // 歩ける地形なのにblockedならentityにふさがれてる
+        let blocked_by_entity = map.blocked[idx] && map.tiles[idx].is_walkable();
+        if map.tiles[idx].is_opaque() || map.view_blocked[idx] || blocked_by_entity {
+            break;
+        }
+    }
+    path
+}
+
+/// 範囲攻撃が当たるマス. 狙うときの予告にも同じものを使う
+pub fn blast_tiles(map: &Map, target: Point, radius: i32) -> Vec<Point> {
+    let mut tiles = rltk::field_of_view(target, radius, map);
+    tiles.retain(|p| p.x > 0 && p.x < map.width - 1 && p.y > 0 && p.y < map.height - 1);
+    tiles
+}
+
+/// 床のそのマスに落ちてるアイテム. 並びは毎回同じになるようにentityの番号順
+pub fn items_at(ecs: &World, point: Point) -> Vec<Entity> {
+    let entities = ecs.entities();
+    let items = ecs.read_storage::<Item>();
+    let positions = ecs.read_storage::<Position>();
+
+    let mut result: Vec<Entity> = (&entities, &items, &positions)
+        .join()
+        .filter(|(_, _, pos)| pos.x == point.x && pos.y == point.y)
+        .map(|(entity, _, _)| entity)
+        .collect();
+    result.sort_by_key(|entity| entity.id());
+    result
+}
+
+/// 自動で拾うかどうかを決めるときのアイテムの種類
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemCategory {
+    Potion,
+    Scroll,
+    Other,
+}
+
+impl ItemCategory {
+    pub const ALL: [ItemCategory; 3] = [
+        ItemCategory::Potion,
+        ItemCategory::Scroll,
+        ItemCategory::Other,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ItemCategory::Potion => "Potions",
+            ItemCategory::Scroll => "Scrolls",
+            ItemCategory::Other => "Other items",
+        }
+    }
+}
+
+/// アイテムの種類. 飲んで回復するのが薬で, 狙って読むのが巻物
+pub fn item_category(ecs: &World, item: Entity) -> ItemCategory {
+    if ecs.read_storage::<ProvidesHealing>().get(item).is_some() {
+        ItemCategory::Potion
+    } else if ecs.read_storage::<Ranged>().get(item).is_some() {
+        ItemCategory::Scroll
+    } else {
+        ItemCategory::Other
+    }
+}
+
+/// 足元のアイテムのうち, 歩いただけで拾うもの
+/// 設定で選んだ種類だけで, 自分で置いたものは拾わない
+pub fn auto_pickup_items(ecs: &World, point: Point) -> Vec<Entity> {
+    let settings = ecs.fetch::<Settings>();
+    if !settings.auto_pickup {
+        return Vec::new();
+    }
+    let dropped = ecs.read_storage::<DroppedByPlayer>();
+    items_at(ecs, point)
+        .into_iter()
+        .filter(|item| dropped.get(*item).is_none())
+        .filter(|item| {
+            settings
+                .auto_pickup_categories
+                .contains(&item_category(ecs, *item))
+        })
+        .collect()
+}
+
+// メニューのa-zで選べる数. 持ち物の上限はこれより大きくできない
+pub const MAX_BACKPACK_SLOTS: usize = 26;
+
 pub struct ItemCollectionSystem {}
 
 impl<'a> System<'a> for ItemCollectionSystem {
     #[allow(clippy::type_complexity)]
     type SystemData = (
         ReadExpect<'a, Entity>,
+        ReadExpect<'a, Settings>,
         WriteExpect<'a, GameLog>,
+        WriteExpect<'a, Statistics>,
+        Entities<'a>,
         WriteStorage<'a, WantsToPickupItem>,
         WriteStorage<'a, Position>,
         ReadStorage<'a, Name>,
         WriteStorage<'a, InBackpack>,
+        WriteStorage<'a, Stackable>,
+        WriteStorage<'a, DroppedByPlayer>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (player_entity, mut gamelog, mut wants_pickup, mut positions, names, mut backpack) =
-            data;
+        let (
+            player_entity,
+            settings,
+            mut gamelog,
+            mut statistics,
+            entities,
+            mut wants_pickup,
+            mut positions,
+            names,
+            mut backpack,
+            mut stackables,
+            mut dropped,
+        ) = data;
 
-        for pickup in wants_pickup.join() {
-            positions.remove(pickup.item);
-            backpack
-                .insert(
-                    pickup.item,
-                    InBackpack {
-                        owner: pickup.collected_by,
-                    },
-                )
-                .expect("Unable to insert backpack entry");
+        let capacity = settings.backpack_slots();
+        let pickups: Vec<(Entity, Entity)> = wants_pickup
+            .join()
+            .map(|pickup| (pickup.collected_by, pickup.item))
+            .collect();
+
+        for (collector, item) in pickups {
+            let item_name = names.get(item).map_or(String::new(), |n| n.name.clone());
+            let found = stackables.get(item).map_or(1, |s| s.count);
+            let first_time = dropped.get(item).is_none();
+
+            // まとめられるアイテムは, 同じ名前のものを持ってればそこに足す
+            let stack = match stackables.get(item) {
+                None => None,
+                Some(_) => (&entities, &backpack, &names, &stackables)
+                    .join()
+                    .find(|(_, pack, name, _)| pack.owner == collector && name.name == item_name)
+                    .map(|(entity, _, _, _)| entity),
+            };
+
+            if let Some(stack) = stack {
+                if let Some(stack) = stackables.get_mut(stack) {
+                    stack.count += found;
+                }
+                positions.remove(item);
+                entities
+                    .delete(item)
+                    .expect("Unable to delete stacked item");
+            } else {
+                // 空いてる一番若い文字に入れる. いっぱいなら拾えない
+                let used: Vec<usize> = backpack
+                    .join()
+                    .filter(|pack| pack.owner == collector)
+                    .map(|pack| pack.slot)
+                    .collect();
+                let free_slot = (0..capacity).find(|slot| !used.contains(slot));
+                let slot = match free_slot {
+                    Some(slot) if used.len() < capacity => slot,
+                    _ => {
+                        if collector == *player_entity {
+                            gamelog.entries.push(format!(
+                                "Your pack is full. You can't pick up {}.",
+                                item_name
+                            ));
+                        }
+                        continue;
+                    }
+                };
+                positions.remove(item);
+                dropped.remove(item);
+                backpack
+                    .insert(
+                        item,
+                        InBackpack {
+                            owner: collector,
+                            slot,
+                        },
+                    )
+                    .expect("Unable to insert backpack entry");
+            }
 
-            if pickup.collected_by == *player_entity {
-                gamelog.entries.push(format!(
-                    "{} was picked up.",
-                    names.get(pickup.item).unwrap().name
-                ));
+            if collector == *player_entity {
+                if first_time {
+                    statistics.items_found += found;
+                }
+                gamelog
+                    .entries
+                    .push(format!("{} was picked up.", item_name));
             }
         }
         wants_pickup.clear();
@@ -58,7 +233,10 @@ impl<'a> System<'a> for ItemUseSystem {
         ReadStorage<'a, ProvidesHealing>,
         ReadStorage<'a, InflictsDamage>,
         ReadStorage<'a, Consumable>,
+        WriteStorage<'a, Stackable>,
         ReadStorage<'a, AreaOfEffect>,
+        ReadStorage<'a, Bolt>,
+        ReadStorage<'a, Position>,
         WriteStorage<'a, Confusion>,
         WriteStorage<'a, CombatStats>,
         WriteStorage<'a, SufferDamage>,
@@ -75,7 +253,10 @@ impl<'a> System<'a> for ItemUseSystem {
             healing,
             inflict_damage,
             consumables,
+            mut stackables,
             aoe,
+            bolts,
+            positions,
             mut confused,
             mut combat_stats,
             mut suffer_damage,
@@ -90,6 +271,14 @@ impl<'a> System<'a> for ItemUseSystem {
                 // 対象を取らないアイテムはプレイヤーに適用する
                 None => targets.push(*player_entity),
                 Some(target) => {
+                    // boltは途中でぶつかったところに当たる
+                    let target = match (bolts.get(useitem.item), positions.get(entity)) {
+                        (Some(_), Some(pos)) => {
+                            let from = Point::new(pos.x, pos.y);
+                            *bolt_path(&map, from, target).last().unwrap_or(&target)
+                        }
+                        _ => target,
+                    };
                     let area_effect = aoe.get(useitem.item);
                     match area_effect {
                         None => {
@@ -101,13 +290,7 @@ impl<'a> System<'a> for ItemUseSystem {
                         }
                         Some(area_effect) => {
                             // aoe
-                            let mut blast_tiles =
-                                rltk::field_of_view(target, area_effect.radius, &*map);
-                            blast_tiles.retain(|p| {
-                                p.x > 0 && p.x < map.width - 1 && p.y > 0 && p.y < map.height - 1
-                            });
-
-                            for tile_idx in blast_tiles.iter() {
+                            for tile_idx in blast_tiles(&map, target, area_effect.radius).iter() {
                                 let idx = map.xy_idx(tile_idx.x, tile_idx.y);
                                 for mob in map.tile_content[idx].iter() {
                                     targets.push(*mob);
@@ -147,7 +330,12 @@ impl<'a> System<'a> for ItemUseSystem {
                 Some(damage) => {
                     used_item = false;
                     for mob in targets.iter() {
-                        SufferDamage::new_damage(&mut suffer_damage, *mob, damage.damage);
+                        SufferDamage::new_damage(
+                            &mut suffer_damage,
+                            *mob,
+                            damage.damage,
+                            Some(entity),
+                        );
                         if entity == *player_entity {
                             let mob_name = names.get(*mob).unwrap();
                             let item_name = names.get(useitem.item).unwrap();
@@ -170,7 +358,6 @@ impl<'a> System<'a> for ItemUseSystem {
                 match causes_confusion {
                     None => {}
                     Some(confusion) => {
-                        used_item = false;
                         for mob in targets.iter() {
                             add_confusion.push((*mob, confusion.turns));
                             if entity == *player_entity {
@@ -197,9 +384,15 @@ impl<'a> System<'a> for ItemUseSystem {
                 match consumable {
                     None => {}
                     Some(_) => {
-                        entities
-                            .delete(useitem.item)
-                            .expect("Delete useitem failed");
+                        // まとめて持ってるなら1つ減らすだけ
+                        match stackables.get_mut(useitem.item) {
+                            Some(stack) if stack.count > 1 => stack.count -= 1,
+                            _ => {
+                                entities
+                                    .delete(useitem.item)
+                                    .expect("Delete useitem failed");
+                            }
+                        }
                     }
                 }
             }
@@ -221,6 +414,8 @@ impl<'a> System<'a> for ItemDropSystem {
         ReadStorage<'a, Name>,
         WriteStorage<'a, Position>,
         WriteStorage<'a, InBackpack>,
+        ReadStorage<'a, Stackable>,
+        WriteStorage<'a, DroppedByPlayer>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -232,6 +427,8 @@ impl<'a> System<'a> for ItemDropSystem {
             names,
             mut positions,
             mut backpack,
+            stackables,
+            mut dropped,
         ) = data;
 
         for (entity, to_drop) in (&entities, &wants_drop).join() {
@@ -253,13 +450,387 @@ impl<'a> System<'a> for ItemDropSystem {
             backpack.remove(to_drop.item);
 
             if entity == *player_entity {
-                gamelog.entries.push(format!(
-                    "{} was put on the ground",
-                    names.get(to_drop.item).unwrap().name
-                ));
+                dropped
+                    .insert(to_drop.item, DroppedByPlayer {})
+                    .expect("Unable to insert DroppedByPlayer");
+                let count = stackables.get(to_drop.item).map_or(1, |s| s.count);
+                let name = &names.get(to_drop.item).unwrap().name;
+                if count > 1 {
+                    gamelog
+                        .entries
+                        .push(format!("{} x{} were put on the ground", name, count));
+                } else {
+                    gamelog
+                        .entries
+                        .push(format!("{} was put on the ground", name));
+                }
             }
         }
 
         wants_drop.clear();
     }
 }
+
+// 投げたアイテムが届く距離と, 当たったときのダメージ
+pub const THROW_RANGE: i32 = 6;
+const THROW_DAMAGE: i32 = 2;
+
+/// まとめて持ってるアイテムから1つだけ取り出す
+/// 1つしかなければそのまま返す. 取り出したものはbackpackにもマップにもない状態になる
+pub fn take_one(ecs: &mut World, item: Entity) -> Entity {
+    let name = match ecs.read_storage::<Stackable>().get(item) {
+        Some(stack) if stack.count > 1 => {
+            ecs.read_storage::<Name>().get(item).map(|n| n.name.clone())
+        }
+        _ => None,
+    };
+    let name = match name {
+        Some(name) => name,
+        None => return item,
+    };
+    // 同じ名前で新しくつくって, 置かれた場所は消しておく
+    // つくれなかったら山ごと返す. 数はつくれたときだけ減らす
+    match spawner::spawn_named(ecs, &name, 0, 0) {
+        Some(one) => {
+            ecs.write_storage::<Position>().remove(one);
+            if let Some(stack) = ecs.write_storage::<Stackable>().get_mut(item) {
+                stack.count -= 1;
+            }
+            one
+        }
+        None => item,
+    }
+}
+
+pub struct ItemThrowSystem {}
+
+impl<'a> System<'a> for ItemThrowSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, GameLog>,
+        ReadExpect<'a, Map>,
+        Entities<'a>,
+        WriteStorage<'a, WantsToThrowItem>,
+        ReadStorage<'a, Name>,
+        ReadStorage<'a, CombatStats>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, InBackpack>,
+        WriteStorage<'a, SufferDamage>,
+        WriteStorage<'a, DroppedByPlayer>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            player_entity,
+            mut gamelog,
+            map,
+            entities,
+            mut wants_throw,
+            names,
+            combat_stats,
+            mut positions,
+            mut backpack,
+            mut suffer_damage,
+            mut dropped,
+        ) = data;
+
+        for (entity, throw) in (&entities, &wants_throw).join() {
+            let from = match positions.get(entity) {
+                Some(pos) => Point::new(pos.x, pos.y),
+                None => continue,
+            };
+            let item_name = names
+                .get(throw.item)
+                .map_or(String::new(), |n| n.name.clone());
+
+            // boltと同じように飛んで, ぶつかったところで落ちる
+            // 壁や閉じたドアに当たったら, その手前に落ちる
+            let path = bolt_path(&map, from, throw.target);
+            let mut landing = from;
+            for point in path.iter() {
+                let idx = map.xy_idx(point.x, point.y);
+                if map.tiles[idx].is_opaque() || map.view_blocked[idx] {
+                    break;
+                }
+                landing = *point;
+            }
+
+            let idx = map.xy_idx(landing.x, landing.y);
+            let mut hit = None;
+            for mob in map.tile_content[idx].iter() {
+                if *mob != entity && combat_stats.get(*mob).is_some() {
+                    hit = Some(*mob);
+                }
+            }
+            if let Some(mob) = hit {
+                SufferDamage::new_damage(&mut suffer_damage, mob, THROW_DAMAGE, Some(entity));
+            }
+
+            // 深い水に落ちても, 沈むのはTerrainSystemにまかせる
+            backpack.remove(throw.item);
+            positions
+                .insert(
+                    throw.item,
+                    Position {
+                        x: landing.x,
+                        y: landing.y,
+                    },
+                )
+                .expect("Unable to insert position");
+            if entity == *player_entity {
+                dropped
+                    .insert(throw.item, DroppedByPlayer {})
+                    .expect("Unable to insert DroppedByPlayer");
+                match hit.and_then(|mob| names.get(mob)) {
+                    Some(mob_name) => gamelog.entries.push(format!(
+                        "The {} hits {}, inflicting {} hp.",
+                        item_name, mob_name.name, THROW_DAMAGE
+                    )),
+                    None => gamelog
+                        .entries
+                        .push(format!("You throw the {}.", item_name)),
+                }
+            }
+        }
+
+        wants_throw.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        describe::FlavourText, keymap::Keymap, map_indexing_system::MapIndexingSystem,
+        terrain_system::TerrainSystem, State, TileType,
+    };
+
+    fn open_map() -> Map {
+        let mut map = Map::new(1, 20, 5);
+        for x in 1..19 {
+            let idx = map.xy_idx(x, 2);
+            map.tiles[idx] = TileType::Floor;
+        }
+        map.populate_blocked();
+        map
+    }
+
+    #[test]
+    fn bolt_stops_at_first_blocker() {
+        let mut map = open_map();
+        let from = Point::new(2, 2);
+        let to = Point::new(10, 2);
+        assert_eq!(bolt_path(&map, from, to).last(), Some(&to));
+
+        // 途中のmonsterで止まる
+        let idx = map.xy_idx(5, 2);
+        map.blocked[idx] = true;
+        let path = bolt_path(&map, from, to);
+        assert_eq!(path.first(), Some(&Point::new(3, 2)));
+        assert_eq!(path.last(), Some(&Point::new(5, 2)));
+
+        // 壁でも止まる
+        let idx = map.xy_idx(4, 2);
+        map.tiles[idx] = TileType::Wall;
+        assert_eq!(bolt_path(&map, from, to).last(), Some(&Point::new(4, 2)));
+    }
+
+    #[test]
+    fn picks_up_several_items_in_one_turn() {
+        let (mut ecs, player) = throw_world();
+        let items: Vec<Entity> = ["Red Potion", "Red Potion", "Magic Missile Scroll"]
+            .iter()
+            .map(|name| spawner::spawn_named(&mut ecs, name, 3, 2).unwrap())
+            .collect();
+        assert_eq!(items_at(&ecs, Point::new(3, 2)), items);
+
+        crate::pickup_items(&mut ecs, &items);
+        ItemCollectionSystem {}.run_now(&ecs);
+        ecs.maintain();
+
+        assert!(items_at(&ecs, Point::new(3, 2)).is_empty());
+        // 2つめの薬は1つめに重なって消える
+        assert!(!ecs.is_alive(items[1]));
+        let positions = ecs.read_storage::<Position>();
+        let backpack = ecs.read_storage::<InBackpack>();
+        for item in [items[0], items[2]] {
+            assert!(positions.get(item).is_none());
+            assert_eq!(backpack.get(item).unwrap().owner, player);
+        }
+        assert_eq!(
+            ecs.read_storage::<Stackable>().get(items[0]).unwrap().count,
+            2
+        );
+        assert_eq!(ecs.fetch::<Statistics>().items_found, 3);
+    }
+
+    #[test]
+    fn auto_pickup_skips_dropped_and_disabled_items() {
+        let mut ecs = World::new();
+        ecs.register::<Item>();
+        ecs.register::<Position>();
+        ecs.register::<ProvidesHealing>();
+        ecs.register::<Ranged>();
+        ecs.register::<DroppedByPlayer>();
+        ecs.insert(Settings {
+            auto_pickup: true,
+            auto_pickup_categories: vec![ItemCategory::Potion],
+            ..Settings::default()
+        });
+
+        let here = Position { x: 3, y: 2 };
+        let potion = ecs
+            .create_entity()
+            .with(Item {})
+            .with(here)
+            .with(ProvidesHealing { heal_amount: 8 })
+            .build();
+        ecs.create_entity()
+            .with(Item {})
+            .with(here)
+            .with(ProvidesHealing { heal_amount: 8 })
+            .with(DroppedByPlayer {})
+            .build();
+        ecs.create_entity()
+            .with(Item {})
+            .with(here)
+            .with(Ranged { range: 6 })
+            .build();
+
+        assert_eq!(auto_pickup_items(&ecs, Point::new(3, 2)), vec![potion]);
+
+        ecs.write_resource::<Settings>().auto_pickup = false;
+        assert!(auto_pickup_items(&ecs, Point::new(3, 2)).is_empty());
+    }
+
+    // (2, 2)にplayerがいて, y = 2の列だけ歩けるworld
+    fn throw_world() -> (World, Entity) {
+        let mut ecs = State::new(
+            None,
+            false,
+            Settings::default(),
+            Keymap::default_bindings(),
+            FlavourText::load().unwrap(),
+        )
+        .ecs;
+        ecs.insert(open_map());
+        let player = *ecs.fetch::<Entity>();
+        ecs.write_storage::<Position>()
+            .insert(player, Position { x: 2, y: 2 })
+            .unwrap();
+        (ecs, player)
+    }
+
+    fn throw(ecs: &mut World, thrower: Entity, target: Point) -> Entity {
+        let item = spawner::spawn_named(ecs, "Red Potion", 0, 0).unwrap();
+        ecs.write_storage::<Position>().remove(item);
+        ecs.write_storage::<InBackpack>()
+            .insert(
+                item,
+                InBackpack {
+                    owner: thrower,
+                    slot: 0,
+                },
+            )
+            .unwrap();
+        ecs.write_storage::<WantsToThrowItem>()
+            .insert(thrower, WantsToThrowItem { item, target })
+            .unwrap();
+        MapIndexingSystem {}.run_now(ecs);
+        ItemThrowSystem {}.run_now(ecs);
+        ecs.maintain();
+        item
+    }
+
+    #[test]
+    fn thrown_item_lands_short_of_a_wall() {
+        let (mut ecs, player) = throw_world();
+        {
+            let mut map = ecs.fetch_mut::<Map>();
+            let idx = map.xy_idx(6, 2);
+            map.tiles[idx] = TileType::Wall;
+        }
+        let item = throw(&mut ecs, player, Point::new(10, 2));
+        assert_eq!(
+            ecs.read_storage::<Position>().get(item),
+            Some(&Position { x: 5, y: 2 })
+        );
+        assert!(ecs.read_storage::<InBackpack>().get(item).is_none());
+    }
+
+    #[test]
+    fn thrown_item_hits_a_monster() {
+        let (mut ecs, player) = throw_world();
+        let orc = spawner::spawn_named(&mut ecs, "Orc", 5, 2).unwrap();
+        let item = throw(&mut ecs, player, Point::new(10, 2));
+
+        let damage = ecs.read_storage::<SufferDamage>();
+        assert_eq!(
+            damage.get(orc).unwrap().amount,
+            vec![(THROW_DAMAGE, Some(player))]
+        );
+        assert_eq!(
+            ecs.read_storage::<Position>().get(item),
+            Some(&Position { x: 5, y: 2 })
+        );
+    }
+
+    #[test]
+    fn thrown_item_sinks_in_deep_water() {
+        let (mut ecs, player) = throw_world();
+        {
+            let mut map = ecs.fetch_mut::<Map>();
+            let idx = map.xy_idx(7, 2);
+            map.tiles[idx] = TileType::DeepWater;
+        }
+        let item = throw(&mut ecs, player, Point::new(7, 2));
+        {
+            let mut map = ecs.fetch_mut::<Map>();
+            let idx = map.xy_idx(7, 2);
+            map.visible_tiles[idx] = true;
+        }
+        TerrainSystem {}.run_now(&ecs);
+        ecs.maintain();
+        assert!(!ecs.is_alive(item));
+        assert!(ecs
+            .fetch::<GameLog>()
+            .entries
+            .last()
+            .unwrap()
+            .contains("sinks"));
+    }
+
+    #[test]
+    fn take_one_keeps_the_stack_when_spawning_fails() {
+        let (mut ecs, _) = throw_world();
+        let potion = spawner::spawn_named(&mut ecs, "Red Potion", 0, 0).unwrap();
+        ecs.write_storage::<Stackable>()
+            .insert(potion, Stackable { count: 2 })
+            .unwrap();
+        let one = take_one(&mut ecs, potion);
+        assert_ne!(one, potion);
+        assert_eq!(
+            ecs.read_storage::<Stackable>().get(potion).unwrap().count,
+            1
+        );
+
+        // 同じ名前でつくれないものは山ごと返して, 数は減らさない
+        ecs.write_storage::<Stackable>()
+            .insert(potion, Stackable { count: 3 })
+            .unwrap();
+        ecs.write_storage::<Name>()
+            .insert(
+                potion,
+                Name {
+                    name: "Mystery Potion".to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(take_one(&mut ecs, potion), potion);
+        assert_eq!(
+            ecs.read_storage::<Stackable>().get(potion).unwrap().count,
+            3
+        );
+    }
+}
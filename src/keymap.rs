@@ -0,0 +1,425 @@
+use super::config;
+use rltk::VirtualKeyCode;
+use serde::de::IntoDeserializer;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+
+const KEYMAP_FILE: &str = "keybindings.toml";
+
+/// playerができる操作. キーはKeymapでこれに変換してから扱う
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Command {
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    MoveUpLeft,
+    MoveUpRight,
+    MoveDownLeft,
+    MoveDownRight,
+    PickUp,
+    Inventory,
+    Drop,
+    CloseDoor,
+    // 押しながら歩くと, 自動で拾う設定でも拾わずに乗るだけ
+    StepOnly,
+    Options,
+    CharacterSheet,
+    Look,
+    // --wizardで起動したときだけ使える
+    WizardConsole,
+    // ここからメニューの中で使う
+    Cancel,
+    NextTarget,
+    Confirm,
+    // wizardのコンソールのカーソルと, 打った文字を1つ消す
+    CursorLeft,
+    CursorRight,
+    CursorUp,
+    CursorDown,
+    Erase,
+}
+
+/// 同じキーでも, 歩いてるときとメニューを開いてるときで別の操作にできる
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum KeyContext {
+    Game,
+    Menu,
+}
+
+impl Command {
+    pub fn context(self) -> KeyContext {
+        match self {
+            Command::Cancel
+            | Command::NextTarget
+            | Command::Confirm
+            | Command::CursorLeft
+            | Command::CursorRight
+            | Command::CursorUp
+            | Command::CursorDown
+            | Command::Erase => KeyContext::Menu,
+            _ => KeyContext::Game,
+        }
+    }
+}
+
+// 設定ファイルで使えるキーの名前. VirtualKeyCodeの名前そのまま
+macro_rules! key_names {
+    ($($key:ident),* $(,)?) => {
+        const KEY_NAMES: &[(&str, VirtualKeyCode)] = &[$((stringify!($key), VirtualKeyCode::$key)),*];
+    };
+}
+
+key_names!(
+    Key1,
+    Key2,
+    Key3,
+    Key4,
+    Key5,
+    Key6,
+    Key7,
+    Key8,
+    Key9,
+    Key0,
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+    G,
+    H,
+    I,
+    J,
+    K,
+    L,
+    M,
+    N,
+    O,
+    P,
+    Q,
+    R,
+    S,
+    T,
+    U,
+    V,
+    W,
+    X,
+    Y,
+    Z,
+    Escape,
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+    Insert,
+    Home,
+    Delete,
+    End,
+    PageDown,
+    PageUp,
+    Left,
+    Up,
+    Right,
+    Down,
+    Back,
+    Return,
+    Space,
+    Tab,
+    Numpad0,
+    Numpad1,
+    Numpad2,
+    Numpad3,
+    Numpad4,
+    Numpad5,
+    Numpad6,
+    Numpad7,
+    Numpad8,
+    Numpad9,
+    NumpadAdd,
+    NumpadDivide,
+    NumpadDecimal,
+    NumpadComma,
+    NumpadEnter,
+    NumpadEquals,
+    NumpadMultiply,
+    NumpadSubtract,
+    Apostrophe,
+    Asterisk,
+    At,
+    Backslash,
+    Caret,
+    Colon,
+    Comma,
+    Convert,
+    Equals,
+    Grave,
+    Kana,
+    Kanji,
+    LBracket,
+    Minus,
+    NoConvert,
+    Period,
+    Plus,
+    RBracket,
+    Semicolon,
+    Slash,
+    Underline,
+    Yen,
+    LShift,
+    RShift,
+    LControl,
+    RControl,
+    LAlt,
+    RAlt,
+);
+
+fn parse_key(name: &str) -> Option<VirtualKeyCode> {
+    KEY_NAMES
+        .iter()
+        .find(|(key_name, _)| *key_name == name)
+        .map(|(_, key)| *key)
+}
+
+fn key_name(key: VirtualKeyCode) -> &'static str {
+    KEY_NAMES
+        .iter()
+        .find(|(_, k)| *k == key)
+        .map_or("?", |(name, _)| *name)
+}
+
+#[derive(Debug)]
+pub enum KeymapError {
+    Io(String),
+    Parse(String),
+    UnknownKey(String),
+    UnknownCommand(String),
+    /// 同じ場面で1つのキーに2つの操作が割り当てられてる
+    Conflict {
+        key: String,
+        first: Command,
+        second: Command,
+    },
+    /// メニューのa-zはアイテムの選択に使うので割り当てられない
+    ReservedKey {
+        key: String,
+        command: Command,
+    },
+}
+
+impl fmt::Display for KeymapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeymapError::Io(e) => write!(f, "Unable to read {}: {}", KEYMAP_FILE, e),
+            KeymapError::Parse(e) => write!(f, "Unable to parse {}: {}", KEYMAP_FILE, e),
+            KeymapError::UnknownKey(key) => write!(f, "Unknown key in {}: {}", KEYMAP_FILE, key),
+            KeymapError::UnknownCommand(command) => {
+                write!(f, "Unknown command in {}: {}", KEYMAP_FILE, command)
+            }
+            KeymapError::Conflict { key, first, second } => write!(
+                f,
+                "Key {} is bound to both {:?} and {:?} in {}",
+                key, first, second, KEYMAP_FILE
+            ),
+            KeymapError::ReservedKey { key, command } => write!(
+                f,
+                "Key {} is used for item selection in menus and cannot be bound to {:?}",
+                key, command
+            ),
+        }
+    }
+}
+
+impl std::error::Error for KeymapError {}
+
+// keybindings.tomlの中身
+// [keys]
+// move_left = ["Left", "Numpad4", "H"]
+// 書かれてない操作はデフォルトのまま
+#[derive(Deserialize)]
+struct KeymapFile {
+    #[serde(default)]
+    keys: HashMap<String, Vec<String>>,
+}
+
+/// キーから操作への対応表
+pub struct Keymap {
+    bindings: Vec<(Command, Vec<VirtualKeyCode>)>,
+}
+
+impl Keymap {
+    pub fn default_bindings() -> Keymap {
+        use VirtualKeyCode::*;
+        Keymap {
+            bindings: vec![
+                (Command::MoveLeft, vec![Left, Numpad4, H]),
+                (Command::MoveRight, vec![Right, Numpad6, L]),
+                (Command::MoveUp, vec![Up, Numpad8, K]),
+                (Command::MoveDown, vec![Down, Numpad2, J]),
+                (Command::MoveUpRight, vec![Numpad9, U]),
+                (Command::MoveUpLeft, vec![Numpad7, Y]),
+                (Command::MoveDownRight, vec![Numpad3, M]),
+                (Command::MoveDownLeft, vec![Numpad1, N]),
+                (Command::PickUp, vec![G]),
+                (Command::Inventory, vec![I]),
+                (Command::Drop, vec![D]),
+                (Command::CloseDoor, vec![C]),
+                (Command::StepOnly, vec![LShift, RShift]),
+                (Command::Options, vec![O]),
+                (Command::CharacterSheet, vec![P]),
+                (Command::Look, vec![X]),
+                (Command::WizardConsole, vec![Grave]),
+                (Command::Cancel, vec![Escape]),
+                (Command::NextTarget, vec![Tab]),
+                (Command::Confirm, vec![Return, NumpadEnter]),
+                (Command::CursorLeft, vec![Left]),
+                (Command::CursorRight, vec![Right]),
+                (Command::CursorUp, vec![Up]),
+                (Command::CursorDown, vec![Down]),
+                (Command::Erase, vec![Back]),
+            ],
+        }
+    }
+
+    /// ユーザーの設定ファイルを読む. なければデフォルト
+    pub fn load() -> Result<Keymap, KeymapError> {
+        let path = match config::config_file(KEYMAP_FILE) {
+            Some(path) if path.exists() => path,
+            _ => return Ok(Keymap::default_bindings()),
+        };
+        let text = fs::read_to_string(&path).map_err(|e| KeymapError::Io(e.to_string()))?;
+        Keymap::from_toml(&text)
+    }
+
+    pub fn from_toml(text: &str) -> Result<Keymap, KeymapError> {
+        let file: KeymapFile =
+            toml::from_str(text).map_err(|e| KeymapError::Parse(e.to_string()))?;
+
+        let mut keymap = Keymap::default_bindings();
+        for (command_name, key_names) in file.keys.iter() {
+            // tomlのテーブルのキーは文字列なので, Commandへはここで変換する
+            let command = Command::deserialize(command_name.as_str().into_deserializer()).map_err(
+                |_: serde::de::value::Error| KeymapError::UnknownCommand(command_name.clone()),
+            )?;
+            let mut keys = Vec::new();
+            for name in key_names.iter() {
+                keys.push(parse_key(name).ok_or_else(|| KeymapError::UnknownKey(name.clone()))?);
+            }
+            if let Some(binding) = keymap.bindings.iter_mut().find(|(c, _)| *c == command) {
+                binding.1 = keys;
+            }
+        }
+
+        keymap.check_conflicts()?;
+        Ok(keymap)
+    }
+
+    fn check_conflicts(&self) -> Result<(), KeymapError> {
+        let mut seen: Vec<(VirtualKeyCode, KeyContext, Command)> = Vec::new();
+        for (command, keys) in self.bindings.iter() {
+            for key in keys.iter() {
+                if command.context() == KeyContext::Menu && rltk::letter_to_option(*key) >= 0 {
+                    return Err(KeymapError::ReservedKey {
+                        key: key_name(*key).to_string(),
+                        command: *command,
+                    });
+                }
+                if let Some((_, _, first)) = seen.iter().find(|(k, context, c)| {
+                    k == key && *context == command.context() && c != command
+                }) {
+                    return Err(KeymapError::Conflict {
+                        key: key_name(*key).to_string(),
+                        first: *first,
+                        second: *command,
+                    });
+                }
+                seen.push((*key, command.context(), *command));
+            }
+        }
+        Ok(())
+    }
+
+    fn command(&self, key: VirtualKeyCode, context: KeyContext) -> Option<Command> {
+        self.bindings
+            .iter()
+            .find(|(command, keys)| command.context() == context && keys.contains(&key))
+            .map(|(command, _)| *command)
+    }
+
+    /// 歩いてるときに押されたキーの操作
+    pub fn game_command(&self, key: VirtualKeyCode) -> Option<Command> {
+        self.command(key, KeyContext::Game)
+    }
+
+    /// メニューを開いてるときに押されたキーの操作
+    pub fn menu_command(&self, key: VirtualKeyCode) -> Option<Command> {
+        self.command(key, KeyContext::Menu)
+    }
+
+    /// その操作のキーのどれかが押しっぱなしになってるか. StepOnlyみたいに他のキーと一緒に押すもの用
+    /// pressedには今押されてるキーを渡す. ゲームの中ではrltk::INPUTのkey_pressed_set
+    pub fn held(&self, command: Command, pressed: &HashSet<VirtualKeyCode>) -> bool {
+        self.bindings
+            .iter()
+            .find(|(c, _)| *c == command)
+            .is_some_and(|(_, keys)| keys.iter().any(|key| pressed.contains(key)))
+    }
+
+    /// 画面に出す用. その操作に割り当てられた最初のキーの名前
+    pub fn key_label(&self, command: Command) -> String {
+        self.bindings
+            .iter()
+            .find(|(c, _)| *c == command)
+            .and_then(|(_, keys)| keys.first())
+            .map_or("(unbound)".to_string(), |key| key_name(*key).to_uppercase())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_bindings_have_no_conflicts() {
+        assert!(Keymap::default_bindings().check_conflicts().is_ok());
+    }
+
+    #[test]
+    fn conflicting_keys_are_rejected() {
+        let result = Keymap::from_toml("[keys]\npick_up = [\"H\"]\n");
+        assert!(matches!(result, Err(KeymapError::Conflict { .. })));
+
+        let result = Keymap::from_toml("[keys]\ncancel = [\"Q\"]\n");
+        assert!(matches!(result, Err(KeymapError::ReservedKey { .. })));
+
+        // 歩くときとメニューで同じキーを使うのはOK
+        let keymap = Keymap::from_toml("[keys]\ncancel = [\"Escape\", \"Numpad4\"]\n").unwrap();
+        assert_eq!(
+            keymap.menu_command(VirtualKeyCode::Numpad4),
+            Some(Command::Cancel)
+        );
+        assert_eq!(
+            keymap.game_command(VirtualKeyCode::Numpad4),
+            Some(Command::MoveLeft)
+        );
+
+        // コンソールのカーソルも付け替えられる
+        let keymap = Keymap::from_toml("[keys]\ncursor_left = [\"Home\"]\n").unwrap();
+        assert_eq!(
+            keymap.menu_command(VirtualKeyCode::Home),
+            Some(Command::CursorLeft)
+        );
+        assert_eq!(keymap.menu_command(VirtualKeyCode::Left), None);
+    }
+}
@@ -0,0 +1,63 @@
+use super::{LightSource, Map, Position, Viewshed};
+use rltk::{field_of_view, DistanceAlg, Point, RGB};
+use specs::prelude::*;
+
+// 普通のフロアの明るさ. 光源の近くだけこれより明るく色づく
+const AMBIENT_LIGHT: f32 = 0.7;
+
+/// 光源から届く光をタイルごとに足し合わせてmap.lightに書き込む
+pub struct LightingSystem {}
+
+impl<'a> System<'a> for LightingSystem {
+    type SystemData = (
+        WriteExpect<'a, Map>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, LightSource>,
+        WriteStorage<'a, Viewshed>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut map, positions, light_sources, mut viewsheds) = data;
+
+        // 暗いフロアには環境光がない
+        let ambient = if map.dark {
+            RGB::from_f32(0., 0., 0.)
+        } else {
+            RGB::from_f32(AMBIENT_LIGHT, AMBIENT_LIGHT, AMBIENT_LIGHT)
+        };
+        let mut light = vec![ambient; map.tiles.len()];
+
+        for (pos, source) in (&positions, &light_sources).join() {
+            let origin = Point::new(pos.x, pos.y);
+            // 壁の向こうには光は届かない
+            let lit_tiles = field_of_view(origin, source.radius, &*map);
+            for tile in lit_tiles.iter() {
+                if tile.x < 0 || tile.x >= map.width || tile.y < 0 || tile.y >= map.height {
+                    continue;
+                }
+                let idx = map.xy_idx(tile.x, tile.y);
+                let distance = DistanceAlg::Pythagoras.distance2d(origin, *tile);
+                // 光源から離れるほど暗くなる. 半径ちょうどのタイルにも少しだけ届く
+                let intensity = 1.0 - distance / (source.radius as f32 + 1.0);
+                if intensity > 0.0 {
+                    let lit = light[idx] + source.colour * intensity;
+                    light[idx] = RGB::from_f32(
+                        f32::min(lit.r, 1.0),
+                        f32::min(lit.g, 1.0),
+                        f32::min(lit.b, 1.0),
+                    );
+                }
+            }
+        }
+
+        if light != map.light {
+            map.light = light;
+            // 暗いフロアでは明かりで見えるものが変わるので, 視界を計算し直させる
+            if map.dark {
+                for viewshed in (&mut viewsheds).join() {
+                    viewshed.dirty = true;
+                }
+            }
+        }
+    }
+}
@@ -13,6 +13,8 @@ mod rect;
 pub use rect::Rect;
 mod visibility_system;
 use visibility_system::VisibilitySystem;
+mod lighting_system;
+use lighting_system::LightingSystem;
 mod monster_ai_system;
 use monster_ai_system::MonsterAI;
 mod map_indexing_system;
@@ -21,12 +23,27 @@ mod melee_combat_system;
 use melee_combat_system::MeleeCombatSystem;
 mod damage_system;
 use damage_system::DamageSystem;
+mod terrain_system;
+use terrain_system::TerrainSystem;
+mod camera;
+mod debug_overlay;
+mod describe;
 mod gui;
 use gui::*;
+mod config;
 mod gamelog;
+mod highscores;
 mod inventory_system;
+mod keymap;
+mod map_builders;
+mod morgue;
+mod settings;
 mod spawner;
-use inventory_system::{ItemCollectionSystem, ItemDropSystem, ItemUseSystem};
+mod statistics;
+mod tileset;
+mod wizard;
+use inventory_system::{ItemCollectionSystem, ItemDropSystem, ItemThrowSystem, ItemUseSystem};
+use map_builders::BuilderKind;
 
 // 待ち状態(相手のターン) or 自分のターン
 #[derive(PartialEq, Copy, Clone, Debug)]
@@ -36,21 +53,134 @@ pub enum RunState {
     PlayerTurn,
     MonsterTurn,
     ShowInventory,
-    ShowDrpoItem,
-    ShowTargeting { range: i32, item: Entity },
+    ShowItemActions {
+        item: Entity,
+    },
+    InspectItem {
+        item: Entity,
+    },
+    // cursorはキーボードで動かす狙いのマス, mouseは前のフレームのマウスの位置
+    ShowTargeting {
+        range: i32,
+        item: Entity,
+        action: gui::ItemAction,
+        cursor: Point,
+        mouse: Point,
+    },
+    ShowPickupMenu,
+    ShowOptions,
+    ShowHighScores,
+    ShowCharacterSheet,
+    Looking {
+        cursor: Point,
+    },
+    WizardConsole {
+        cursor: Point,
+    },
+    GameOver,
 }
+const DARK_FLOOR_MIN_DEPTH: i32 = 3;
+const DARK_FLOOR_PERCENT: i32 = 25;
+
 pub struct State {
     ecs: World,
+    // --builder で指定されたら, どのフロアでもそのアルゴリズムでマップをつくる
+    forced_builder: Option<BuilderKind>,
+    // 起動したときのフォント. 設定を変えても次に起動するまではこのまま
+    font_in_use: settings::Font,
+    // --wizard で起動したらデバッグ用のコンソールが使える
+    wizard: bool,
 }
 
 impl State {
+    /// ゲームを始める前のworldをつくる. コンポーネントとリソースを登録してplayerを置く
+    /// マップはまだ空なので, このあとgenerate_world_mapを呼ぶ
+    fn new(
+        forced_builder: Option<BuilderKind>,
+        wizard: bool,
+        settings: settings::Settings,
+        keymap: keymap::Keymap,
+        flavour: describe::FlavourText,
+    ) -> State {
+        let font_in_use = settings.font;
+        let mut ecs = World::new();
+        ecs.register::<Position>();
+        ecs.register::<Renderable>();
+        ecs.register::<Player>();
+        ecs.register::<Monster>();
+        ecs.register::<Viewshed>();
+        ecs.register::<Name>();
+        ecs.register::<BlocksTile>();
+        ecs.register::<BlocksVisibility>();
+        ecs.register::<LightSource>();
+        ecs.register::<Door>();
+        ecs.register::<CombatStats>();
+        ecs.register::<SufferDamage>();
+        ecs.register::<WantsToMelee>();
+        ecs.register::<EnteredTile>();
+        ecs.insert(RunState::PreRun);
+        ecs.insert(wizard::ConsoleInput::default());
+        ecs.insert(debug_overlay::DebugOverlays::default());
+        ecs.insert(keymap);
+        ecs.insert(settings);
+        ecs.insert(statistics::Statistics::default());
+        ecs.insert(flavour);
+        ecs.insert(highscores::HighScores::default());
+        ecs.insert(gamelog::GameLog {
+            entries: vec!["Welcome to Rusty Roguelike".to_string()],
+        });
+        ecs.register::<Item>();
+        ecs.register::<ProvidesHealing>();
+        ecs.register::<InBackpack>();
+        ecs.register::<WantsToPickupItem>();
+        ecs.register::<DroppedByPlayer>();
+        ecs.register::<WantsToUseItem>();
+        ecs.register::<WantsToDropItem>();
+        ecs.register::<WantsToThrowItem>();
+        ecs.register::<Consumable>();
+        ecs.register::<Stackable>();
+        ecs.register::<ProvideHealing>();
+        ecs.register::<Ranged>();
+        ecs.register::<InflictsDamage>();
+        ecs.register::<AreaOfEffect>();
+        ecs.register::<Bolt>();
+        ecs.register::<Confusion>();
+        ecs.register::<GodMode>();
+        ecs.register::<Swimmer>();
+        // 登録したあとすぐにつくらないと, playerを置いたことを見逃す
+        let index_readers = map_indexing_system::IndexReaders::new(&ecs);
+        ecs.insert(index_readers);
+
+        ecs.insert(rltk::RandomNumberGenerator::new());
+        ecs.insert(Map::new(1, MAPWIDTH, MAPHEIGHT));
+        ecs.insert(Point::new(0, 0));
+
+        // playerをつくる
+        // 初期位置はgenerate_world_mapで決まる
+        let player_entity = spawner::player(&mut ecs, 0, 0);
+        // plyaerのentityをecsに登録して,かんたんに参照できるようにする
+        ecs.insert(player_entity);
+
+        State {
+            ecs,
+            forced_builder,
+            font_in_use,
+            wizard,
+        }
+    }
+
     fn run_systems(&mut self) {
+        // 暗いフロアでは明かりで視界が変わるので, 視界より先に計算する
+        let mut lighting = LightingSystem {};
+        lighting.run_now(&self.ecs);
         let mut vis = VisibilitySystem {};
         vis.run_now(&self.ecs);
         let mut mob = MonsterAI {};
         mob.run_now(&self.ecs);
         let mut mapindex = MapIndexingSystem {};
         mapindex.run_now(&self.ecs);
+        let mut terrain = TerrainSystem {};
+        terrain.run_now(&self.ecs);
         let mut melee_combat = MeleeCombatSystem {};
         melee_combat.run_now(&self.ecs);
         let mut damage_system = DamageSystem {};
@@ -61,22 +191,196 @@ impl State {
         potions_system.run_now(&self.ecs);
         let mut drop_items = ItemDropSystem {};
         drop_items.run_now(&self.ecs);
+        let mut throw_items = ItemThrowSystem {};
+        throw_items.run_now(&self.ecs);
 
         // システムにより何らかの変更がqueueに入れられたら,即座に世界に適用する
         self.ecs.maintain();
     }
+
+    // アイテムのメニューで選んだことを始める. 狙いが要るならターゲット選択へ
+    fn start_item_action(&mut self, ctx: &Rltk, item: Entity, action: gui::ItemAction) -> RunState {
+        let player_entity = *self.ecs.fetch::<Entity>();
+        let mouse = Point::new(ctx.mouse_pos().0, ctx.mouse_pos().1);
+        let range = match action {
+            gui::ItemAction::Use => self.ecs.read_storage::<Ranged>().get(item).map(|r| r.range),
+            gui::ItemAction::Throw => Some(inventory_system::THROW_RANGE),
+            _ => None,
+        };
+        if let Some(range) = range {
+            return RunState::ShowTargeting {
+                range,
+                item,
+                action,
+                cursor: gui::initial_target(&self.ecs, range),
+                mouse,
+            };
+        }
+
+        match action {
+            gui::ItemAction::Inspect => return RunState::InspectItem { item },
+            gui::ItemAction::Drop => {
+                self.ecs
+                    .write_storage::<WantsToDropItem>()
+                    .insert(player_entity, WantsToDropItem { item })
+                    .expect("Unable to insert intent");
+            }
+            _ => {
+                self.ecs
+                    .write_storage::<WantsToUseItem>()
+                    .insert(player_entity, WantsToUseItem { item, target: None })
+                    .expect("Unable to insert intent");
+            }
+        }
+        RunState::PlayerTurn
+    }
+
+    // playerが倒れたときの後始末. morgueファイルを書いて, どこに書いたかをログに出す
+    // 記録も残して, ゲームオーバーの画面に出す
+    fn game_over(&mut self) {
+        let message = match morgue::write_morgue(&self.ecs) {
+            Ok(path) => format!("Morgue file written to {}", path.display()),
+            Err(e) => e.to_string(),
+        };
+        self.ecs
+            .write_resource::<gamelog::GameLog>()
+            .entries
+            .push(message);
+
+        let entry = highscores::ScoreEntry::from_world(&self.ecs);
+        if let Err(e) = highscores::record(&entry) {
+            self.ecs
+                .write_resource::<gamelog::GameLog>()
+                .entries
+                .push(e.to_string());
+        }
+        self.load_high_scores(Some(entry));
+    }
+
+    // 記録の画面のために記録を読んでおく. 読めなければ空の表にしてログに出す
+    fn load_high_scores(&mut self, latest: Option<highscores::ScoreEntry>) {
+        let entries = match highscores::load() {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.ecs
+                    .write_resource::<gamelog::GameLog>()
+                    .entries
+                    .push(e.to_string());
+                Vec::new()
+            }
+        };
+        self.ecs.insert(highscores::HighScores { entries, latest });
+    }
+
+    // フロアを移るときに消すentity. playerとplayerの持ち物以外ぜんぶ
+    fn entities_to_remove_on_level_change(&mut self) -> Vec<Entity> {
+        let entities = self.ecs.entities();
+        let player = self.ecs.read_storage::<Player>();
+        let backpack = self.ecs.read_storage::<InBackpack>();
+        let player_entity = self.ecs.fetch::<Entity>();
+
+        let mut to_delete: Vec<Entity> = Vec::new();
+        for entity in entities.join() {
+            if player.get(entity).is_some() {
+                continue;
+            }
+            if let Some(bp) = backpack.get(entity) {
+                if bp.owner == *player_entity {
+                    continue;
+                }
+            }
+            to_delete.push(entity);
+        }
+        to_delete
+    }
+
+    /// 新しいフロアをつくって, monsterとitemを湧かせてplayerを初期位置に置く
+    fn generate_world_map(&mut self, new_depth: i32) {
+        let to_delete = self.entities_to_remove_on_level_change();
+        for target in to_delete {
+            self.ecs
+                .delete_entity(target)
+                .expect("Unable to delete entity");
+        }
+
+        let mut builder;
+        {
+            let mut rng = self.ecs.write_resource::<rltk::RandomNumberGenerator>();
+            builder = match self.forced_builder {
+                Some(kind) => map_builders::builder_for(kind, new_depth, MAPWIDTH, MAPHEIGHT),
+                None => map_builders::random_builder(new_depth, MAPWIDTH, MAPHEIGHT, &mut rng),
+            };
+            builder.build_map(&mut rng);
+        }
+        {
+            let mut worldmap_resource = self.ecs.write_resource::<Map>();
+            *worldmap_resource = builder.get_map();
+            // 3階以降は, ときどき明かりのない暗いフロアになる
+            let mut rng = self.ecs.write_resource::<rltk::RandomNumberGenerator>();
+            worldmap_resource.dark =
+                new_depth >= DARK_FLOOR_MIN_DEPTH && rng.roll_dice(1, 100) <= DARK_FLOOR_PERCENT;
+        }
+
+        for region in builder.get_spawn_regions().iter() {
+            spawner::spawn_region(&mut self.ecs, region);
+        }
+        let map_width = self.ecs.fetch::<Map>().width as usize;
+        for (idx, name) in builder.get_spawn_list().iter() {
+            let x = (*idx % map_width) as i32;
+            let y = (*idx / map_width) as i32;
+            if spawner::spawn_named(&mut self.ecs, name, x, y).is_none() {
+                rltk::console::log(format!("Unknown spawn: {}", name));
+            }
+        }
+
+        // 最初の視界計算より前に, 閉じたドアなどをmapに反映しておく
+        let mut mapindex = MapIndexingSystem {};
+        mapindex.run_now(&self.ecs);
+
+        // playerを初期位置に置いて, 視界を計算し直させる
+        let player_start = builder.get_starting_position();
+        let mut player_position = self.ecs.write_resource::<Point>();
+        *player_position = Point::new(player_start.x, player_start.y);
+        let mut position_components = self.ecs.write_storage::<Position>();
+        let player_entity = self.ecs.fetch::<Entity>();
+        if let Some(player_pos_comp) = position_components.get_mut(*player_entity) {
+            *player_pos_comp = player_start;
+        }
+        let mut viewshed_components = self.ecs.write_storage::<Viewshed>();
+        if let Some(vs) = viewshed_components.get_mut(*player_entity) {
+            vs.dirty = true;
+        }
+    }
+}
+
+// `--builder <name>` だけ読む
+fn parse_forced_builder() -> Result<Option<BuilderKind>, String> {
+    let args: Vec<String> = std::env::args().collect();
+    match args.iter().position(|arg| arg == "--builder") {
+        None => Ok(None),
+        Some(i) => match args.get(i + 1) {
+            None => Err("--builder requires a value".to_string()),
+            Some(name) => name.parse::<BuilderKind>().map(Some),
+        },
+    }
 }
 
 impl GameState for State {
     fn tick(&mut self, ctx: &mut Rltk) {
-        ctx.cls();
+        for layer in [camera::MAP_LAYER, camera::TILE_LAYER, camera::UI_LAYER] {
+            ctx.set_active_console(layer);
+            ctx.cls();
+        }
         let mut newrunstate;
         {
             let runstate = self.ecs.fetch::<RunState>();
             newrunstate = *runstate;
         }
 
-        draw_map(&self.ecs, ctx);
+        camera::render_camera(&self.ecs, ctx);
+        // ここから先のメニューやログ欄はマップより上のレイヤーに描く
+        ctx.set_active_console(camera::UI_LAYER);
+        debug_overlay::render_overlays(&self.ecs, ctx);
 
         // 今のターンに応じてゲームを動かして次のターンに遷移する
         match newrunstate {
@@ -96,6 +400,7 @@ impl GameState for State {
                 newrunstate = RunState::MonsterTurn;
             }
             RunState::MonsterTurn => {
+                self.ecs.write_resource::<statistics::Statistics>().turns += 1;
                 self.run_systems();
                 self.ecs.maintain();
                 newrunstate = RunState::AwaitingInput;
@@ -106,71 +411,152 @@ impl GameState for State {
                     gui::ItemMenuResult::Cancel => newrunstate = RunState::AwaitingInput,
                     gui::ItemMenuResult::NoResponse => {}
                     gui::ItemMenuResult::Selected => {
-                        let item_entity = result.1.unwrap();
-                        let is_ranged = self.ecs.read_storage::<Ranged>();
-                        let is_item_ranged = is_ranged.get(item_entity);
-
-                        if let Some(is_item_ranged) = is_item_ranged {
-                            newrunstate = RunState::ShowTargeting {
-                                range: is_item_ranged.range,
-                                item: item_entity,
-                            };
+                        newrunstate = RunState::ShowItemActions {
+                            item: result.1.unwrap(),
+                        };
+                    }
+                }
+            }
+            RunState::ShowItemActions { item } => {
+                let result = gui::item_action_menu(self, ctx, item);
+                match result.0 {
+                    // 持ち物の一覧に戻る
+                    gui::ItemMenuResult::Cancel => newrunstate = RunState::ShowInventory,
+                    gui::ItemMenuResult::NoResponse => {}
+                    gui::ItemMenuResult::Selected => {
+                        newrunstate = self.start_item_action(ctx, item, result.1.unwrap());
+                    }
+                }
+            }
+            RunState::InspectItem { item } => {
+                if gui::inspect_item(self, ctx, item) == gui::ItemMenuResult::Cancel {
+                    newrunstate = RunState::ShowItemActions { item };
+                }
+            }
+            RunState::ShowTargeting {
+                range,
+                item,
+                action,
+                cursor,
+                mouse,
+            } => {
+                match gui::ranged_target(self, ctx, range, item, action, cursor, mouse) {
+                    // 範囲外クリックとか選択キャンセルとかしたら再度playerの入力待ちに戻る
+                    gui::TargetResult::Cancel => newrunstate = RunState::AwaitingInput,
+                    gui::TargetResult::NoResponse => {}
+                    gui::TargetResult::Moved { cursor, mouse } => {
+                        newrunstate = RunState::ShowTargeting {
+                            range,
+                            item,
+                            action,
+                            cursor,
+                            mouse,
+                        }
+                    }
+                    gui::TargetResult::Selected(target) => {
+                        let player_entity = *self.ecs.fetch::<Entity>();
+                        if action == gui::ItemAction::Throw {
+                            // まとめて持ってるなら1つだけ投げる
+                            let item = inventory_system::take_one(&mut self.ecs, item);
+                            self.ecs
+                                .write_storage::<WantsToThrowItem>()
+                                .insert(player_entity, WantsToThrowItem { item, target })
+                                .expect("Unable to insert intent");
                         } else {
-                            let mut intent = self.ecs.write_storage::<WantsToUseItem>();
-                            intent
+                            self.ecs
+                                .write_storage::<WantsToUseItem>()
                                 .insert(
-                                    *self.ecs.fetch::<Entity>(),
+                                    player_entity,
                                     WantsToUseItem {
-                                        item: item_entity,
-                                        target: None,
+                                        item,
+                                        target: Some(target),
                                     },
                                 )
-                                .expect("Uneable to insert intent");
-                            newrunstate = RunState::PlayerTurn;
+                                .expect("Unable to insert intent");
                         }
+                        newrunstate = RunState::PlayerTurn;
                     }
                 }
             }
-            RunState::ShowDrpoItem => {
-                let result = gui::drop_item_menu(self, ctx);
-                match result.0 {
+            RunState::ShowPickupMenu => {
+                let (result, items) = gui::pickup_menu(self, ctx);
+                match result {
                     gui::ItemMenuResult::Cancel => newrunstate = RunState::AwaitingInput,
                     gui::ItemMenuResult::NoResponse => {}
                     gui::ItemMenuResult::Selected => {
-                        let item_entity = result.1.unwrap();
-                        let mut intent = self.ecs.write_storage::<WantsToDropItem>();
-                        intent
-                            .insert(
-                                *self.ecs.fetch::<Entity>(),
-                                WantsToDropItem { item: item_entity },
-                            )
-                            .expect("Unable to insert intent");
-
+                        player::pickup_items(&mut self.ecs, &items);
                         newrunstate = RunState::PlayerTurn;
                     }
                 }
             }
-            RunState::ShowTargeting { range, item } => {
-                let result = gui::ranged_target(self, ctx, range);
-                match result.0 {
-                    // 範囲外クリックとか選択キャンセルとかしたら再度playerの入力待ちに戻る
-                    gui::ItemMenuResult::Cancel => newrunstate = RunState::AwaitingInput,
-                    gui::ItemMenuResult::NoResponse => {}
-                    gui::ItemMenuResult::Selected => {
-                        let mut intent = self.ecs.write_storage::<WantsToUseItem>();
-                        intent
-                            .insert(
-                                *self.ecs.fetch::<Entity>(),
-                                WantsToUseItem {
-                                    item,
-                                    target: result.1,
-                                },
-                            )
-                            .expect("Unable to insert intent");
-                        newrunstate = RunState::PlayerTurn;
+            RunState::ShowCharacterSheet => {
+                if gui::show_character_sheet(self, ctx) == gui::ItemMenuResult::Cancel {
+                    newrunstate = RunState::AwaitingInput;
+                }
+            }
+            RunState::Looking { cursor } => match gui::look_mode(self, ctx, cursor) {
+                gui::LookResult::Cancel => newrunstate = RunState::AwaitingInput,
+                gui::LookResult::NoResponse => {}
+                gui::LookResult::MoveCursor(cursor) => newrunstate = RunState::Looking { cursor },
+            },
+            RunState::ShowOptions => match gui::options_menu(self, ctx) {
+                gui::OptionsResult::Cancel => newrunstate = RunState::AwaitingInput,
+                gui::OptionsResult::NoResponse => {}
+                gui::OptionsResult::HighScores => {
+                    self.load_high_scores(None);
+                    newrunstate = RunState::ShowHighScores;
+                }
+                // 変えたらすぐ画面に反映して保存する
+                gui::OptionsResult::Changed => {
+                    let settings = self.ecs.fetch::<settings::Settings>();
+                    settings.apply(ctx);
+                    if let Err(e) = settings.save() {
+                        self.ecs
+                            .write_resource::<gamelog::GameLog>()
+                            .entries
+                            .push(e.to_string());
                     }
                 }
+            },
+            RunState::ShowHighScores => {
+                if gui::high_scores(self, ctx) == gui::ItemMenuResult::Cancel {
+                    newrunstate = RunState::ShowOptions;
+                }
             }
+            RunState::WizardConsole { cursor } => match gui::wizard_console(self, ctx, cursor) {
+                gui::ConsoleResult::Cancel => newrunstate = RunState::AwaitingInput,
+                gui::ConsoleResult::NoResponse => {}
+                gui::ConsoleResult::MoveCursor(cursor) => {
+                    newrunstate = RunState::WizardConsole { cursor }
+                }
+                // 打ったコマンドを実行してコンソールを閉じる. 視界などはPreRunで計算しなおす
+                gui::ConsoleResult::Submit => {
+                    let line =
+                        std::mem::take(&mut self.ecs.write_resource::<wizard::ConsoleInput>().text);
+                    let message = wizard::run_command(self, &line, cursor);
+                    self.ecs
+                        .write_resource::<gamelog::GameLog>()
+                        .entries
+                        .push(message);
+                    newrunstate = RunState::PreRun;
+                }
+            },
+            RunState::GameOver => {
+                if gui::game_over(self, ctx) == gui::ItemMenuResult::Cancel {
+                    ctx.quit();
+                }
+            }
+        }
+
+        // 倒れたターンに1回だけゲームオーバーにする. 死因はDamageSystemが倒れたときに入れる
+        let player_died = self
+            .ecs
+            .fetch::<statistics::Statistics>()
+            .cause_of_death
+            .is_some();
+        if player_died && newrunstate != RunState::GameOver {
+            self.game_over();
+            newrunstate = RunState::GameOver;
         }
 
         {
@@ -180,78 +566,30 @@ impl GameState for State {
         }
         damage_system::delete_the_dead(&mut self.ecs);
 
-        let positions = self.ecs.read_storage::<Position>();
-        let renderables = self.ecs.read_storage::<Renderable>();
-        let map = self.ecs.fetch::<Map>();
-
-        let mut data = (&positions, &renderables).join().collect::<Vec<_>>();
-        data.sort_by(|&a, &b| b.1.render_order.cmp(&a.1.render_order));
-        for (pos, render) in data.iter() {
-            let idx = map.xy_idx(pos.x, pos.y);
-            if map.visible_tiles[idx] {
-                ctx.set(pos.x, pos.y, render.fg, render.bg, render.glyph)
-            }
-        }
+        let log_length = self.ecs.fetch::<settings::Settings>().log_length;
+        self.ecs
+            .write_resource::<gamelog::GameLog>()
+            .trim(log_length);
 
         draw_ui(&self.ecs, ctx);
     }
 }
 
 fn main() -> rltk::BError {
-    use rltk::RltkBuilder;
-    let mut context = RltkBuilder::simple80x50()
-        .with_title("風来のたぬぽん")
-        .build()?;
-    context.with_post_scanlines(true);
-
-    let mut gs = State { ecs: World::new() };
-    gs.ecs.register::<Position>();
-    gs.ecs.register::<Renderable>();
-    gs.ecs.register::<Player>();
-    gs.ecs.register::<Monster>();
-    gs.ecs.register::<Viewshed>();
-    gs.ecs.register::<Name>();
-    gs.ecs.register::<BlocksTile>();
-    gs.ecs.register::<CombatStats>();
-    gs.ecs.register::<SufferDamage>();
-    gs.ecs.register::<WantsToMelee>();
-    gs.ecs.insert(RunState::PreRun);
-    gs.ecs.insert(gamelog::GameLog {
-        entries: vec!["Welcome to Rusty Roguelike".to_string()],
-    });
-    gs.ecs.register::<Item>();
-    gs.ecs.register::<ProvidesHealing>();
-    gs.ecs.register::<InBackpack>();
-    gs.ecs.register::<WantsToPickupItem>();
-    gs.ecs.register::<WantsToUseItem>();
-    gs.ecs.register::<WantsToDropItem>();
-    gs.ecs.register::<Consumable>();
-    gs.ecs.register::<ProvideHealing>();
-    gs.ecs.register::<Ranged>();
-    gs.ecs.register::<InflictsDamage>();
-    gs.ecs.register::<AreaOfEffect>();
-    gs.ecs.register::<Confusion>();
-
-    let map: Map = Map::new_map_rooms_and_corridors();
-    let (player_x, player_y) = map.rooms[0].center();
-
-    // playerをつくる
-    // 初期位置を部屋の真ん中にする
-    let player_entity = spawner::player(&mut gs.ecs, player_x, player_y);
-
-    gs.ecs.insert(rltk::RandomNumberGenerator::new());
-    // Mobをつくる
-    // skip(1) で最初の部屋にはmob配置しないようにする
-    // playerが配置されるから
-    for room in map.rooms.iter().skip(1) {
-        let (_x, _y) = room.center();
-        spawner::spawn_room(&mut gs.ecs, room);
-    }
-    gs.ecs.insert(map);
-    // ecsのsystemにplayerの居場所を伝える
-    gs.ecs.insert(Point::new(player_x, player_y));
-    // plyaerのentityをecsに登録して,かんたんに参照できるようにする
-    gs.ecs.insert(player_entity);
+    let settings = settings::Settings::load()?;
+    let mut context = settings.build_context("風来のたぬぽん")?;
+    settings.apply(&mut context);
+
+    let forced_builder = parse_forced_builder().map_err(|e| e.to_string())?;
+    let wizard = std::env::args().any(|arg| arg == "--wizard");
+    let mut gs = State::new(
+        forced_builder,
+        wizard,
+        settings,
+        keymap::Keymap::load()?,
+        describe::FlavourText::load()?,
+    );
+    gs.generate_world_map(1);
 
     rltk::main_loop(context, gs)
 }
@@ -1,18 +1,75 @@
 use super::Rect;
-use rltk::{Algorithm2D, BaseMap, Point, RandomNumberGenerator, Rltk, RGB};
+use rltk::{Algorithm2D, BaseMap, Point, RGB};
 use specs::prelude::*;
-use std::cmp::{max, min};
 
-pub const MAPWIDTH: usize = 80;
-pub const MAPHEIGHT: usize = 43;
-pub const MAPCOUNT: usize = MAPHEIGHT * MAPWIDTH;
+// フロアの標準の大きさ. 画面より大きくてもカメラがスクロールする
+pub const MAPWIDTH: i32 = 120;
+pub const MAPHEIGHT: i32 = 64;
 
-#[derive(PartialEq, Copy, Clone)]
+#[derive(PartialEq, Copy, Clone, Debug)]
 pub enum TileType {
     Wall,
     Floor,
+    // 泳げる(Swimmer)entityしか入れない. ここに来たアイテムは沈んでなくなる(TerrainSystem)
+    DeepWater,
+    ShallowWater,
+    // 入ると燃える
+    Lava,
+    Rubble,
+    // 深い水の上に架かってる
+    Bridge,
 }
 
+impl TileType {
+    pub const ALL: [TileType; 7] = [
+        TileType::Wall,
+        TileType::Floor,
+        TileType::DeepWater,
+        TileType::ShallowWater,
+        TileType::Lava,
+        TileType::Rubble,
+        TileType::Bridge,
+    ];
+
+    /// 歩いて入れるか. 深い水は泳げないと入れない
+    pub fn is_walkable(self) -> bool {
+        !matches!(self, TileType::Wall | TileType::DeepWater)
+    }
+
+    pub fn is_opaque(self) -> bool {
+        self == TileType::Wall
+    }
+
+    /// A*で使う移動コストの倍率. 溶岩は歩けるけどなるべく避けたい
+    pub fn movement_cost(self) -> f32 {
+        match self {
+            TileType::ShallowWater => 1.5,
+            TileType::Rubble => 2.0,
+            TileType::Lava => 10.0,
+            _ => 1.0,
+        }
+    }
+}
+
+/// 視界の決め方
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum VisibilityMode {
+    /// 半径で決まる普通の視界
+    Radius,
+    /// 部屋に入ると部屋全体が見えて, 通路では隣のタイルしか見えない
+    Rooms,
+}
+
+/// 空間インデックスに入ってるentityの, いる場所と道やまわりの見通しをふさぐか
+#[derive(Clone, Copy, Debug)]
+pub struct IndexEntry {
+    pub entity: Entity,
+    pub idx: usize,
+    pub blocks_move: bool,
+    pub blocks_view: bool,
+}
+
+#[derive(Clone)]
 pub struct Map {
     pub tiles: Vec<TileType>,
     pub rooms: Vec<Rect>,
@@ -21,8 +78,24 @@ pub struct Map {
     pub revealed_tiles: Vec<bool>,
     pub visible_tiles: Vec<bool>,
     pub blocked: Vec<bool>,
+    // 閉じたドアみたいに, 床だけど向こうが見えないタイル
+    pub view_blocked: Vec<bool>,
     // そのタイルがなにかメモっておく
     pub tile_content: Vec<Vec<Entity>>,
+    // 空間インデックス. entityの番号ごとに, どのタイルにどう入ってるか
+    // blockedとview_blockedはタイルごとにふさいでるentityを数えて決める
+    index: Vec<Option<IndexEntry>>,
+    move_blockers: Vec<u32>,
+    view_blockers: Vec<u32>,
+    // falseならまだ誰も入ってない. MapIndexingSystemが最初に全部入れる
+    pub index_built: bool,
+    // タイルごとの明るさと色. LightingSystemが毎ターン計算する
+    pub light: Vec<RGB>,
+    // 暗いフロア. 環境光がなくて, 明かりのあるところと足元まわりしか見えない
+    pub dark: bool,
+    pub visibility_mode: VisibilityMode,
+    // 何階か
+    pub depth: i32,
 }
 
 impl Map {
@@ -30,122 +103,181 @@ impl Map {
         (y as usize * self.width as usize) + x as usize
     }
 
-    // 部屋を作る. 与えられたrectのタイルをすべて床にする
-    fn apply_room_to_map(&mut self, room: &Rect) {
-        for y in room.y1 + 1..=room.y2 {
-            for x in room.x1 + 1..=room.x2 {
-                let idx = self.xy_idx(x, y);
-                self.tiles[idx] = TileType::Floor;
-            }
+    /// 全部壁のまっさらなMapをつくる. 中身はMapBuilderが彫っていく
+    pub fn new(new_depth: i32, width: i32, height: i32) -> Map {
+        let map_tile_count = (width * height) as usize;
+        Map {
+            tiles: vec![TileType::Wall; map_tile_count],
+            rooms: Vec::new(),
+            width,
+            height,
+            revealed_tiles: vec![false; map_tile_count],
+            visible_tiles: vec![false; map_tile_count],
+            blocked: vec![false; map_tile_count],
+            view_blocked: vec![false; map_tile_count],
+            tile_content: vec![Vec::new(); map_tile_count],
+            index: Vec::new(),
+            move_blockers: vec![0; map_tile_count],
+            view_blockers: vec![0; map_tile_count],
+            index_built: false,
+            light: vec![RGB::from_f32(1., 1., 1.); map_tile_count],
+            dark: false,
+            visibility_mode: VisibilityMode::Radius,
+            depth: new_depth,
         }
     }
 
-    fn apply_horizontal_tunnel(&mut self, x1: i32, x2: i32, y: i32) {
-        for x in min(x1, x2)..=max(x1, x2) {
-            let idx = self.xy_idx(x, y);
-            if idx > 0 && idx < self.width as usize * self.height as usize {
-                self.tiles[idx as usize] = TileType::Floor;
-            }
+    /// idxのタイルに入れるか. 泳げるなら深い水にも入れる
+    pub fn can_enter(&self, idx: usize, swimmer: bool) -> bool {
+        if swimmer && self.tiles[idx] == TileType::DeepWater {
+            self.move_blockers[idx] == 0
+        } else {
+            !self.blocked[idx]
         }
     }
 
-    fn apply_vertical_tunnel(&mut self, y1: i32, y2: i32, x: i32) {
-        for y in min(y1, y2)..=max(y1, y2) {
-            let idx = self.xy_idx(x, y);
-            if idx > 0 && idx < self.width as usize * self.height as usize {
-                self.tiles[idx as usize] = TileType::Floor;
-            }
+    fn is_exit_valid(&self, x: i32, y: i32, swimmer: bool) -> bool {
+        // Mapからはみ出していればexitとしてふさわしくない
+        if x < 1 || x > self.width - 1 || y < 1 || y > self.height - 1 {
+            return false;
         }
+        self.can_enter(self.xy_idx(x, y), swimmer)
     }
 
-    /// Makes a new map using the algorithm from http://rogueliketutorials.com/tutorials/tcod/part-3/
-    /// This gives a handful of random rooms and corridors joining them together.
-    pub fn new_map_rooms_and_corridors() -> Map {
-        let mut map = Map {
-            tiles: vec![TileType::Wall; MAPCOUNT],
-            rooms: Vec::new(),
-            width: MAPWIDTH as i32,
-            height: MAPHEIGHT as i32,
-            revealed_tiles: vec![false; MAPCOUNT],
-            visible_tiles: vec![false; MAPCOUNT],
-            blocked: vec![false; MAPCOUNT],
-            tile_content: vec![Vec::new(); MAPCOUNT],
+    fn exits(&self, idx: usize, swimmer: bool) -> rltk::SmallVec<[(usize, f32); 10]> {
+        let mut exits = rltk::SmallVec::new();
+        let x = idx as i32 % self.width;
+        let y = idx as i32 / self.width;
+        let w = self.width as usize;
+
+        // 行き先の地形に応じてコストを変える
+        let cost = |dest: usize, base: f32| base * self.tiles[dest].movement_cost();
+
+        // 上下左右
+        if self.is_exit_valid(x - 1, y, swimmer) {
+            exits.push((idx - 1, cost(idx - 1, 1.0)))
+        };
+        if self.is_exit_valid(x + 1, y, swimmer) {
+            exits.push((idx + 1, cost(idx + 1, 1.0)))
+        };
+        if self.is_exit_valid(x, y - 1, swimmer) {
+            exits.push((idx - w, cost(idx - w, 1.0)))
+        };
+        if self.is_exit_valid(x, y + 1, swimmer) {
+            exits.push((idx + w, cost(idx + w, 1.0)))
         };
 
-        // ↓で作ったお部屋たちを登録してく
-        const MAX_ROOMS: i32 = 30;
-        const MIN_SIZE: i32 = 6;
-        const MAX_SIZE: i32 = 10;
-
-        let mut rng = RandomNumberGenerator::new();
-
-        for _ in 0..MAX_ROOMS {
-            let w = rng.range(MIN_SIZE, MAX_SIZE);
-            let h = rng.range(MIN_SIZE, MAX_SIZE);
-            let x = rng.roll_dice(1, MAPWIDTH as i32 - w - 1) - 1;
-            let y = rng.roll_dice(1, MAPHEIGHT as i32 - h - 1) - 1;
-            let new_room = Rect::new(x, y, w, h);
-            let mut ok = true;
-
-            // 作ろうとしてる部屋が既存の部屋と重なってたら作るのやめる
-            for other_room in map.rooms.iter() {
-                if new_room.intersect(other_room) {
-                    ok = false
-                }
-            }
-            if ok {
-                map.apply_room_to_map(&new_room);
-
-                // 部屋を廊下でつなげる
-                if !map.rooms.is_empty() {
-                    // その部屋の真ん中の座標を得る
-                    let (new_x, new_y) = new_room.center();
-                    // 1つ前の部屋の真ん中の座標を得る
-                    let (prev_x, prev_y) = map.rooms[map.rooms.len() - 1].center();
-
-                    // 0か1のサイコロふる
-                    if rng.range(0, 2) == 1 {
-                        map.apply_horizontal_tunnel(prev_x, new_x, prev_y);
-                        map.apply_vertical_tunnel(prev_y, new_y, new_x);
-                    } else {
-                        map.apply_vertical_tunnel(prev_y, new_y, prev_x);
-                        map.apply_horizontal_tunnel(prev_x, new_x, new_y);
-                    }
-                }
-
-                map.rooms.push(new_room);
-            }
+        // 斜め方向
+        if self.is_exit_valid(x - 1, y - 1, swimmer) {
+            exits.push(((idx - w) - 1, cost((idx - w) - 1, 1.45)))
+        };
+        if self.is_exit_valid(x + 1, y - 1, swimmer) {
+            exits.push(((idx - w) + 1, cost((idx - w) + 1, 1.45)))
+        };
+        if self.is_exit_valid(x - 1, y + 1, swimmer) {
+            exits.push(((idx + w) - 1, cost((idx + w) - 1, 1.45)))
+        };
+        if self.is_exit_valid(x + 1, y + 1, swimmer) {
+            exits.push(((idx + w) + 1, cost((idx + w) + 1, 1.45)))
+        };
+
+        exits
+    }
+
+    // タイルがブロックされてる？ 地形と, そこにいる道をふさぐentityで決まる
+    pub fn populate_blocked(&mut self) {
+        for idx in 0..self.tiles.len() {
+            self.refresh_tile(idx);
         }
+    }
 
-        map
+    fn refresh_tile(&mut self, idx: usize) {
+        self.blocked[idx] = !self.tiles[idx].is_walkable() || self.move_blockers[idx] > 0;
+        self.view_blocked[idx] = self.view_blockers[idx] > 0;
     }
 
-    fn is_exit_valid(&self, x: i32, y: i32) -> bool {
-        // Mapからはみ出していればexitとしてふさわしくない
-        if x < 1 || x > self.width - 1 || y < 1 || y > self.height - 1 {
-            return false;
+    /// entityが今インデックスのどこに入ってるか
+    pub fn indexed(&self, entity: Entity) -> Option<IndexEntry> {
+        self.index
+            .get(entity.id() as usize)
+            .copied()
+            .flatten()
+            .filter(|entry| entry.entity == entity)
+    }
+
+    /// entityをidxのタイルに入れる. もうどこかに入ってたらそこから出してから入れる
+    pub fn index_entity(
+        &mut self,
+        entity: Entity,
+        idx: usize,
+        blocks_move: bool,
+        blocks_view: bool,
+    ) {
+        self.unindex_id(entity.id());
+        let slot = entity.id() as usize;
+        if self.index.len() <= slot {
+            self.index.resize(slot + 1, None);
         }
-        let idx = self.xy_idx(x, y);
-        !self.blocked[idx]
+        self.index[slot] = Some(IndexEntry {
+            entity,
+            idx,
+            blocks_move,
+            blocks_view,
+        });
+        self.tile_content[idx].push(entity);
+        self.move_blockers[idx] += blocks_move as u32;
+        self.view_blockers[idx] += blocks_view as u32;
+        self.refresh_tile(idx);
     }
 
-    // タイルがブロックされてる？
-    pub fn populate_blocked(&mut self) {
-        for (i, tile) in self.tiles.iter_mut().enumerate() {
-            self.blocked[i] = *tile == TileType::Wall;
+    /// その番号のentityをインデックスから出す. 消されたentityは番号しかわからないので番号で引く
+    pub fn unindex_id(&mut self, id: u32) {
+        let entry = match self.index.get_mut(id as usize).and_then(|e| e.take()) {
+            Some(entry) => entry,
+            None => return,
+        };
+        self.tile_content[entry.idx].retain(|e| *e != entry.entity);
+        self.move_blockers[entry.idx] -= entry.blocks_move as u32;
+        self.view_blockers[entry.idx] -= entry.blocks_view as u32;
+        self.refresh_tile(entry.idx);
+    }
+
+    /// インデックスに入ってるentityを別のタイルに動かす
+    /// 同じターンにあとから動くmonsterがぶつからないように, その場でblockedも変わる
+    pub fn move_entity(&mut self, entity: Entity, idx: usize) {
+        if let Some(entry) = self.indexed(entity) {
+            self.index_entity(entity, idx, entry.blocks_move, entry.blocks_view);
         }
     }
 
-    pub fn clear_content_index(&mut self) {
+    /// インデックスを空にする. 地形のblockedだけが残る
+    pub fn clear_index(&mut self) {
+        self.index.clear();
         for content in self.tile_content.iter_mut() {
             content.clear();
         }
+        self.move_blockers.iter_mut().for_each(|n| *n = 0);
+        self.view_blockers.iter_mut().for_each(|n| *n = 0);
+        self.populate_blocked();
+    }
+
+    /// (x, y)がいる部屋. 部屋の中の床だけで, 部屋を囲む壁や入口は含まない
+    pub fn room_at(&self, x: i32, y: i32) -> Option<&Rect> {
+        self.rooms
+            .iter()
+            .find(|r| x > r.x1 && x <= r.x2 && y > r.y1 && y <= r.y2)
+    }
+
+    /// 明かりで照らされてるか
+    pub fn is_lit(&self, idx: usize) -> bool {
+        let light = self.light[idx];
+        light.r > 0.0 || light.g > 0.0 || light.b > 0.0
     }
 }
 
 impl BaseMap for Map {
     fn is_opaque(&self, idx: usize) -> bool {
-        self.tiles[idx as usize] == TileType::Wall
+        self.tiles[idx].is_opaque() || self.view_blocked[idx]
     }
 
     fn get_pathing_distance(&self, idx1: usize, idx2: usize) -> f32 {
@@ -157,40 +289,30 @@ impl BaseMap for Map {
 
     // bracket-pathfindingの経路探索(ぐらふ)を使うために必要なメソッド
     fn get_available_exits(&self, idx: usize) -> rltk::SmallVec<[(usize, f32); 10]> {
-        let mut exits = rltk::SmallVec::new();
-        let x = idx as i32 % self.width;
-        let y = idx as i32 / self.width;
-        let w = self.width as usize;
+        self.exits(idx, false)
+    }
+}
 
-        // 上下左右
-        if self.is_exit_valid(x - 1, y) {
-            exits.push((idx - 1, 1.0))
-        };
-        if self.is_exit_valid(x + 1, y) {
-            exits.push((idx + 1, 1.0))
-        };
-        if self.is_exit_valid(x, y - 1) {
-            exits.push((idx - w, 1.0))
-        };
-        if self.is_exit_valid(x, y + 1) {
-            exits.push((idx + w, 1.0))
-        };
+/// 泳げるentityから見たマップ. 深い水も通れるものとして道を探す
+pub struct SwimmingMap<'a>(pub &'a Map);
 
-        // 斜め方向
-        if self.is_exit_valid(x - 1, y - 1) {
-            exits.push(((idx - w) - 1, 1.45))
-        };
-        if self.is_exit_valid(x + 1, y - 1) {
-            exits.push(((idx - w) + 1, 1.45))
-        };
-        if self.is_exit_valid(x - 1, y + 1) {
-            exits.push(((idx + w) - 1, 1.45))
-        };
-        if self.is_exit_valid(x + 1, y + 1) {
-            exits.push(((idx + w) + 1, 1.45))
-        };
+impl<'a> BaseMap for SwimmingMap<'a> {
+    fn is_opaque(&self, idx: usize) -> bool {
+        self.0.is_opaque(idx)
+    }
 
-        exits
+    fn get_pathing_distance(&self, idx1: usize, idx2: usize) -> f32 {
+        self.0.get_pathing_distance(idx1, idx2)
+    }
+
+    fn get_available_exits(&self, idx: usize) -> rltk::SmallVec<[(usize, f32); 10]> {
+        self.0.exits(idx, true)
+    }
+}
+
+impl<'a> Algorithm2D for SwimmingMap<'a> {
+    fn dimensions(&self) -> Point {
+        self.0.dimensions()
     }
 }
 
@@ -200,41 +322,42 @@ impl Algorithm2D for Map {
     }
 }
 
-// TileTypeのスライスを足す
-pub fn draw_map(ecs: &World, ctx: &mut Rltk) {
-    let map = ecs.fetch::<Map>();
-
-    let mut y = 0;
-    let mut x = 0;
-
-    // enumerate()で(index, value)のタプルを生成する
-    for (idx, tile) in map.tiles.iter().enumerate() {
-        // タイルの種類に応じてタイルを描く
-        if map.revealed_tiles[idx] {
-            let glyph;
-            let mut fg;
-            match tile {
-                TileType::Floor => {
-                    glyph = rltk::to_cp437('.');
-                    fg = RGB::from_f32(0.0, 0.5, 0.5);
-                }
-                TileType::Wall => {
-                    glyph = rltk::to_cp437('#');
-                    fg = RGB::from_f32(0., 1.0, 0.);
-                }
-            }
+/// 地形の名前. look modeで使う
+pub fn tile_name(tile: TileType) -> &'static str {
+    match tile {
+        TileType::Floor => "Floor",
+        TileType::Wall => "Wall",
+        TileType::DeepWater => "Deep water",
+        TileType::ShallowWater => "Shallow water",
+        TileType::Lava => "Lava",
+        TileType::Rubble => "Rubble",
+        TileType::Bridge => "Bridge",
+    }
+}
 
-            if !map.visible_tiles[idx] {
-                fg = fg.to_greyscale()
-            }
-            ctx.set(x, y, fg, RGB::from_f32(0., 0., 0.), glyph);
-        }
+/// 地形ごとの見た目. 見たことのあるマスは灰色で描くので, 文字だけでも見分けられるようにする
+pub fn tile_glyph(tile: TileType) -> (rltk::FontCharType, RGB) {
+    match tile {
+        TileType::Floor => (rltk::to_cp437('.'), RGB::from_f32(0.0, 0.5, 0.5)),
+        TileType::Wall => (rltk::to_cp437('#'), RGB::from_f32(0., 1.0, 0.)),
+        TileType::DeepWater => (rltk::to_cp437('≈'), RGB::named(rltk::DODGER_BLUE)),
+        TileType::ShallowWater => (rltk::to_cp437('~'), RGB::named(rltk::CYAN)),
+        TileType::Lava => (rltk::to_cp437('^'), RGB::named(rltk::ORANGE_RED)),
+        TileType::Rubble => (rltk::to_cp437(';'), RGB::named(rltk::SADDLE_BROWN)),
+        TileType::Bridge => (rltk::to_cp437('='), RGB::named(rltk::SANDY_BROWN)),
+    }
+}
 
-        // 座標を動かす
-        x += 1;
-        if x > 79 {
-            x = 0;
-            y += 1;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_terrain_has_its_own_glyph() {
+        for (i, a) in TileType::ALL.iter().enumerate() {
+            for b in TileType::ALL.iter().skip(i + 1) {
+                assert_ne!(tile_glyph(*a).0, tile_glyph(*b).0, "{:?} and {:?}", a, b);
+            }
         }
     }
 }
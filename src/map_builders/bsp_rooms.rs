@@ -0,0 +1,121 @@
+use super::{
+    apply_horizontal_tunnel, apply_room_to_map, apply_vertical_tunnel, room_entrances,
+    room_spawn_regions, Map, MapBuilder, Position, Rect, VisibilityMode,
+};
+use rltk::RandomNumberGenerator;
+
+// これより小さい区画はもう分割しない
+const MIN_LEAF_SIZE: i32 = 10;
+const MIN_ROOM_SIZE: i32 = 4;
+
+/// 二分空間分割(BSP)で区画を切って, 区画ごとに部屋を1つ置く
+pub struct BspRoomsBuilder {
+    map: Map,
+    starting_position: Position,
+    spawn_list: Vec<(usize, String)>,
+}
+
+impl MapBuilder for BspRoomsBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        let mut leaves: Vec<Rect> = Vec::new();
+        let whole = Rect::new(1, 1, self.map.width - 3, self.map.height - 3);
+        split_leaf(whole, rng, &mut leaves);
+
+        for leaf in leaves.iter() {
+            let room = self.room_in_leaf(leaf, rng);
+            apply_room_to_map(&mut self.map, &room);
+
+            // 分割した順に並んでるので, 1つ前の部屋とつなげれば兄弟の区画同士がつながる
+            if !self.map.rooms.is_empty() {
+                let (new_x, new_y) = room.center();
+                let (prev_x, prev_y) = self.map.rooms[self.map.rooms.len() - 1].center();
+                if rng.range(0, 2) == 1 {
+                    apply_horizontal_tunnel(&mut self.map, prev_x, new_x, prev_y);
+                    apply_vertical_tunnel(&mut self.map, prev_y, new_y, new_x);
+                } else {
+                    apply_vertical_tunnel(&mut self.map, prev_y, new_y, prev_x);
+                    apply_horizontal_tunnel(&mut self.map, prev_x, new_x, new_y);
+                }
+            }
+
+            self.map.rooms.push(room);
+        }
+
+        // 部屋と通路でできたフロアなので, 部屋に入ると部屋全体が見える
+        self.map.visibility_mode = VisibilityMode::Rooms;
+
+        // 部屋の入口にドアをつける
+        for idx in room_entrances(&self.map) {
+            self.spawn_list.push((idx, "Door".to_string()));
+        }
+
+        let (x, y) = self.map.rooms[0].center();
+        self.starting_position = Position { x, y };
+    }
+
+    fn get_map(&self) -> Map {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        self.starting_position
+    }
+
+    fn get_spawn_regions(&self) -> Vec<Vec<usize>> {
+        room_spawn_regions(&self.map)
+    }
+
+    fn get_spawn_list(&self) -> Vec<(usize, String)> {
+        self.spawn_list.clone()
+    }
+}
+
+impl BspRoomsBuilder {
+    pub fn new(new_depth: i32, width: i32, height: i32) -> BspRoomsBuilder {
+        BspRoomsBuilder {
+            map: Map::new(new_depth, width, height),
+            starting_position: Position { x: 0, y: 0 },
+            spawn_list: Vec::new(),
+        }
+    }
+
+    // 区画の中に収まる大きさの部屋をランダムに置く
+    fn room_in_leaf(&self, leaf: &Rect, rng: &mut RandomNumberGenerator) -> Rect {
+        let leaf_w = leaf.x2 - leaf.x1;
+        let leaf_h = leaf.y2 - leaf.y1;
+        let w = rng.range(MIN_ROOM_SIZE, leaf_w - 1);
+        let h = rng.range(MIN_ROOM_SIZE, leaf_h - 1);
+        let x = leaf.x1 + rng.range(0, leaf_w - w);
+        let y = leaf.y1 + rng.range(0, leaf_h - h);
+        Rect::new(x, y, w, h)
+    }
+}
+
+// 区画を縦か横に2つに割っていく. 割れなくなった区画がleavesに入る
+fn split_leaf(leaf: Rect, rng: &mut RandomNumberGenerator, leaves: &mut Vec<Rect>) {
+    let w = leaf.x2 - leaf.x1;
+    let h = leaf.y2 - leaf.y1;
+    let can_split_x = w >= MIN_LEAF_SIZE * 2;
+    let can_split_y = h >= MIN_LEAF_SIZE * 2;
+
+    // 長い方を優先して割る. どっちも割れるならサイコロで決める
+    let split_x = match (can_split_x, can_split_y) {
+        (false, false) => {
+            leaves.push(leaf);
+            return;
+        }
+        (true, false) => true,
+        (false, true) => false,
+        (true, true) => rng.range(0, 2) == 1,
+    };
+
+    if split_x {
+        let cut = rng.range(MIN_LEAF_SIZE, w - MIN_LEAF_SIZE + 1);
+        split_leaf(Rect::new(leaf.x1, leaf.y1, cut, h), rng, leaves);
+        split_leaf(Rect::new(leaf.x1 + cut, leaf.y1, w - cut, h), rng, leaves);
+    } else {
+        let cut = rng.range(MIN_LEAF_SIZE, h - MIN_LEAF_SIZE + 1);
+        split_leaf(Rect::new(leaf.x1, leaf.y1, w, cut), rng, leaves);
+        split_leaf(Rect::new(leaf.x1, leaf.y1 + cut, w, h - cut), rng, leaves);
+    }
+}
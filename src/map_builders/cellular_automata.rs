@@ -0,0 +1,142 @@
+use super::{
+    cull_unreachable, grid_spawn_regions, nearest_floor, reachable_from, Map, MapBuilder, Position,
+    TileType,
+};
+use rltk::RandomNumberGenerator;
+
+const ITERATIONS: i32 = 15;
+const SPAWN_CHUNK_SIZE: i32 = 10;
+// 初期位置から行ける床がこれより少ない洞窟はつくり直す. 小さいマップでは内側の1/4まで下げる
+const MIN_REACHABLE_FLOOR: usize = 500;
+// これだけつくり直しても足りなければ, 一番広くつながってた洞窟を使う
+const MAX_ATTEMPTS: i32 = 20;
+
+/// セルオートマトンで洞窟っぽいマップをつくる
+pub struct CellularAutomataBuilder {
+    map: Map,
+    starting_position: Position,
+}
+
+impl MapBuilder for CellularAutomataBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        let interior =
+            (i32::max(self.map.width - 2, 0) * i32::max(self.map.height - 2, 0)) as usize;
+        let wanted = usize::min(MIN_REACHABLE_FLOOR, interior / 4);
+
+        // (タイル, 初期位置, 初期位置から行ける床の数)
+        let mut best: Option<(Vec<TileType>, usize, usize)> = None;
+        for _ in 0..MAX_ATTEMPTS {
+            self.generate_cave(rng);
+            let start_idx = match nearest_floor(&self.map, self.map.width / 2, self.map.height / 2)
+            {
+                Some(start_idx) => start_idx,
+                None => continue,
+            };
+            let reachable = reachable_from(&self.map, start_idx)
+                .iter()
+                .filter(|r| **r)
+                .count();
+            let better = match &best {
+                Some((_, _, best_reachable)) => reachable > *best_reachable,
+                None => true,
+            };
+            if better {
+                best = Some((self.map.tiles.clone(), start_idx, reachable));
+            }
+            if reachable >= wanted {
+                break;
+            }
+        }
+
+        let start_idx = match best {
+            Some((tiles, start_idx, _)) => {
+                self.map.tiles = tiles;
+                start_idx
+            }
+            // 床が1つもできなかったら, 真ん中だけ床にする
+            None => {
+                let idx = self.map.xy_idx(self.map.width / 2, self.map.height / 2);
+                self.map.tiles[idx] = TileType::Floor;
+                idx
+            }
+        };
+        // 初期位置とつながってない小部屋は埋めてしまう
+        cull_unreachable(&mut self.map, start_idx);
+        self.starting_position = Position {
+            x: start_idx as i32 % self.map.width,
+            y: start_idx as i32 / self.map.width,
+        };
+    }
+
+    fn get_map(&self) -> Map {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        self.starting_position
+    }
+
+    fn get_spawn_regions(&self) -> Vec<Vec<usize>> {
+        let start_idx = self
+            .map
+            .xy_idx(self.starting_position.x, self.starting_position.y);
+        grid_spawn_regions(&self.map, start_idx, SPAWN_CHUNK_SIZE)
+    }
+}
+
+impl CellularAutomataBuilder {
+    pub fn new(new_depth: i32, width: i32, height: i32) -> CellularAutomataBuilder {
+        CellularAutomataBuilder {
+            map: Map::new(new_depth, width, height),
+            starting_position: Position { x: 0, y: 0 },
+        }
+    }
+
+    fn generate_cave(&mut self, rng: &mut RandomNumberGenerator) {
+        // まず45%くらいを床にしてばらまく
+        for y in 1..self.map.height - 1 {
+            for x in 1..self.map.width - 1 {
+                let idx = self.map.xy_idx(x, y);
+                self.map.tiles[idx] = if rng.roll_dice(1, 100) > 55 {
+                    TileType::Floor
+                } else {
+                    TileType::Wall
+                };
+            }
+        }
+
+        // 周りの壁の数で床か壁かを決め直すのを繰り返すと, なめらかな洞窟になる
+        for _ in 0..ITERATIONS {
+            let mut new_tiles = self.map.tiles.clone();
+            for y in 1..self.map.height - 1 {
+                for x in 1..self.map.width - 1 {
+                    let neighbors = self.count_wall_neighbors(x, y);
+                    let idx = self.map.xy_idx(x, y);
+                    new_tiles[idx] = if neighbors > 4 || neighbors == 0 {
+                        TileType::Wall
+                    } else {
+                        TileType::Floor
+                    };
+                }
+            }
+            self.map.tiles = new_tiles;
+        }
+    }
+
+    // 周り8マスのうち壁がいくつあるか
+    fn count_wall_neighbors(&self, x: i32, y: i32) -> i32 {
+        let mut count = 0;
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                if dx == 0 && dy == 0 {
+                    continue;
+                }
+                let idx = self.map.xy_idx(x + dx, y + dy);
+                if self.map.tiles[idx] == TileType::Wall {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+}
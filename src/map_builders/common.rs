@@ -0,0 +1,181 @@
+use super::{Map, Rect, TileType};
+use std::cmp::{max, min};
+
+// 部屋を作る. 与えられたrectのタイルをすべて床にする
+pub fn apply_room_to_map(map: &mut Map, room: &Rect) {
+    for y in room.y1 + 1..=room.y2 {
+        for x in room.x1 + 1..=room.x2 {
+            let idx = map.xy_idx(x, y);
+            map.tiles[idx] = TileType::Floor;
+        }
+    }
+}
+
+pub fn apply_horizontal_tunnel(map: &mut Map, x1: i32, x2: i32, y: i32) {
+    for x in min(x1, x2)..=max(x1, x2) {
+        let idx = map.xy_idx(x, y);
+        if idx > 0 && idx < map.width as usize * map.height as usize {
+            map.tiles[idx] = TileType::Floor;
+        }
+    }
+}
+
+pub fn apply_vertical_tunnel(map: &mut Map, y1: i32, y2: i32, x: i32) {
+    for y in min(y1, y2)..=max(y1, y2) {
+        let idx = map.xy_idx(x, y);
+        if idx > 0 && idx < map.width as usize * map.height as usize {
+            map.tiles[idx] = TileType::Floor;
+        }
+    }
+}
+
+/// (x, y)にいちばん近い床タイルのindexを返す
+pub fn nearest_floor(map: &Map, x: i32, y: i32) -> Option<usize> {
+    let target = rltk::Point::new(x, y);
+    map.tiles
+        .iter()
+        .enumerate()
+        .filter(|(_, tile)| **tile == TileType::Floor)
+        .map(|(idx, _)| {
+            let p = rltk::Point::new(idx as i32 % map.width, idx as i32 / map.width);
+            (
+                idx,
+                rltk::DistanceAlg::PythagorasSquared.distance2d(target, p),
+            )
+        })
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+        .map(|(idx, _)| idx)
+}
+
+/// 部屋を持つマップ用. 部屋の中の床をそのままspawn regionにする
+/// 最初の部屋にはplayerが配置されるから含めない
+pub fn room_spawn_regions(map: &Map) -> Vec<Vec<usize>> {
+    map.rooms
+        .iter()
+        .skip(1)
+        .map(|room| {
+            let mut region = Vec::new();
+            for y in room.y1 + 1..=room.y2 {
+                for x in room.x1 + 1..=room.x2 {
+                    let idx = map.xy_idx(x, y);
+                    if map.tiles[idx] == TileType::Floor {
+                        region.push(idx);
+                    }
+                }
+            }
+            region
+        })
+        .collect()
+}
+
+/// 部屋を持たないマップ用. マップを格子状に区切って, 区画ごとの床をspawn regionにする
+/// playerの初期位置を含む区画は含めない
+pub fn grid_spawn_regions(map: &Map, start: usize, chunk_size: i32) -> Vec<Vec<usize>> {
+    let mut regions = Vec::new();
+    for chunk_y in (0..map.height).step_by(chunk_size as usize) {
+        for chunk_x in (0..map.width).step_by(chunk_size as usize) {
+            let mut region = Vec::new();
+            for y in chunk_y..i32::min(chunk_y + chunk_size, map.height) {
+                for x in chunk_x..i32::min(chunk_x + chunk_size, map.width) {
+                    let idx = map.xy_idx(x, y);
+                    if map.tiles[idx] == TileType::Floor {
+                        region.push(idx);
+                    }
+                }
+            }
+            if !region.is_empty() && !region.contains(&start) {
+                regions.push(region);
+            }
+        }
+    }
+    regions
+}
+
+/// startから上下左右に歩いて行ける床タイルに印をつける
+pub fn reachable_from(map: &Map, start: usize) -> Vec<bool> {
+    let mut reachable = vec![false; map.tiles.len()];
+    if !map.tiles[start].is_walkable() {
+        return reachable;
+    }
+
+    let mut open_list = vec![start];
+    reachable[start] = true;
+    while let Some(idx) = open_list.pop() {
+        let x = idx as i32 % map.width;
+        let y = idx as i32 / map.width;
+        for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)].iter() {
+            let nx = x + dx;
+            let ny = y + dy;
+            if nx < 0 || nx >= map.width || ny < 0 || ny >= map.height {
+                continue;
+            }
+            let n_idx = map.xy_idx(nx, ny);
+            if !reachable[n_idx] && map.tiles[n_idx].is_walkable() {
+                reachable[n_idx] = true;
+                open_list.push(n_idx);
+            }
+        }
+    }
+    reachable
+}
+
+/// startから行けない床(歩ける地形)をぜんぶ壁にする. 壁にしたタイルの数を返す
+pub fn cull_unreachable(map: &mut Map, start: usize) -> usize {
+    let reachable = reachable_from(map, start);
+    let mut culled = 0;
+    for (idx, tile) in map.tiles.iter_mut().enumerate() {
+        if tile.is_walkable() && !reachable[idx] {
+            *tile = TileType::Wall;
+            culled += 1;
+        }
+    }
+    culled
+}
+
+/// 歩ける地形がすべてstartから歩いて行けるか
+pub fn is_fully_connected(map: &Map, start: usize) -> bool {
+    let reachable = reachable_from(map, start);
+    map.tiles
+        .iter()
+        .enumerate()
+        .all(|(idx, tile)| !tile.is_walkable() || reachable[idx])
+}
+
+/// 部屋と廊下のつなぎ目を探す. ドアを置く場所
+/// 部屋を囲む壁の輪の上にある床のうち, 両脇が壁になってるところ
+pub fn room_entrances(map: &Map) -> Vec<usize> {
+    let mut entrances: Vec<usize> = Vec::new();
+    for room in map.rooms.iter() {
+        let mut ring = Vec::new();
+        for x in room.x1..=room.x2 + 1 {
+            ring.push((x, room.y1));
+            ring.push((x, room.y2 + 1));
+        }
+        for y in room.y1 + 1..=room.y2 {
+            ring.push((room.x1, y));
+            ring.push((room.x2 + 1, y));
+        }
+
+        for (x, y) in ring {
+            if x < 1 || x > map.width - 2 || y < 1 || y > map.height - 2 {
+                continue;
+            }
+            let idx = map.xy_idx(x, y);
+            if map.tiles[idx] == TileType::Floor
+                && is_doorway(map, x, y)
+                && !entrances.contains(&idx)
+            {
+                entrances.push(idx);
+            }
+        }
+    }
+    entrances
+}
+
+fn is_doorway(map: &Map, x: i32, y: i32) -> bool {
+    let is_wall = |x, y| map.tiles[map.xy_idx(x, y)] == TileType::Wall;
+    let walls_left_right = is_wall(x - 1, y) && is_wall(x + 1, y);
+    let walls_up_down = is_wall(x, y - 1) && is_wall(x, y + 1);
+    (walls_left_right && !is_wall(x, y - 1) && !is_wall(x, y + 1))
+        || (walls_up_down && !is_wall(x - 1, y) && !is_wall(x + 1, y))
+}
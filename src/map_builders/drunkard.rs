@@ -0,0 +1,81 @@
+use super::{grid_spawn_regions, Map, MapBuilder, Position, TileType};
+use rltk::RandomNumberGenerator;
+
+// 床がこの割合になるまで酔っぱらいを歩かせる
+const DESIRED_FLOOR_PERCENT: usize = 50;
+const DRUNKARD_LIFETIME: i32 = 400;
+const SPAWN_CHUNK_SIZE: i32 = 10;
+
+/// 酔っぱらいの千鳥足で床を掘る
+/// どの酔っぱらいも中央から歩き出すので, 掘られた床はすべてつながっている
+pub struct DrunkardsWalkBuilder {
+    map: Map,
+    starting_position: Position,
+}
+
+impl MapBuilder for DrunkardsWalkBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        self.starting_position = Position {
+            x: self.map.width / 2,
+            y: self.map.height / 2,
+        };
+        let start_idx = self
+            .map
+            .xy_idx(self.starting_position.x, self.starting_position.y);
+        self.map.tiles[start_idx] = TileType::Floor;
+
+        let total_tiles = (self.map.width * self.map.height) as usize;
+        let desired_floor_tiles = total_tiles * DESIRED_FLOOR_PERCENT / 100;
+
+        while self.floor_count() < desired_floor_tiles {
+            let mut x = self.starting_position.x;
+            let mut y = self.starting_position.y;
+
+            for _ in 0..DRUNKARD_LIFETIME {
+                let idx = self.map.xy_idx(x, y);
+                self.map.tiles[idx] = TileType::Floor;
+
+                // 外周は掘らない
+                match rng.roll_dice(1, 4) {
+                    1 if x > 1 => x -= 1,
+                    2 if x < self.map.width - 2 => x += 1,
+                    3 if y > 1 => y -= 1,
+                    4 if y < self.map.height - 2 => y += 1,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn get_map(&self) -> Map {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        self.starting_position
+    }
+
+    fn get_spawn_regions(&self) -> Vec<Vec<usize>> {
+        let start_idx = self
+            .map
+            .xy_idx(self.starting_position.x, self.starting_position.y);
+        grid_spawn_regions(&self.map, start_idx, SPAWN_CHUNK_SIZE)
+    }
+}
+
+impl DrunkardsWalkBuilder {
+    pub fn new(new_depth: i32, width: i32, height: i32) -> DrunkardsWalkBuilder {
+        DrunkardsWalkBuilder {
+            map: Map::new(new_depth, width, height),
+            starting_position: Position { x: 0, y: 0 },
+        }
+    }
+
+    fn floor_count(&self) -> usize {
+        self.map
+            .tiles
+            .iter()
+            .filter(|tile| **tile == TileType::Floor)
+            .count()
+    }
+}
@@ -0,0 +1,86 @@
+use super::{grid_spawn_regions, Map, MapBuilder, Position, TileType};
+use rltk::RandomNumberGenerator;
+
+const SPAWN_CHUNK_SIZE: i32 = 10;
+
+/// 再帰的バックトラックで迷路をつくる
+/// 奇数座標のタイルを「セル」とみなして, セル同士の間の壁を壊しながら進む
+pub struct MazeBuilder {
+    map: Map,
+    starting_position: Position,
+}
+
+impl MapBuilder for MazeBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        let cells_w = (self.map.width - 1) / 2;
+        let cells_h = (self.map.height - 1) / 2;
+        let mut visited = vec![false; (cells_w * cells_h) as usize];
+
+        // 再帰だとスタックが深くなりすぎるので, 自前のスタックで辿る
+        let mut stack: Vec<(i32, i32)> = vec![(0, 0)];
+        visited[0] = true;
+        self.carve_cell(0, 0);
+
+        while let Some(&(cx, cy)) = stack.last() {
+            let mut neighbors = Vec::new();
+            for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)].iter() {
+                let nx = cx + dx;
+                let ny = cy + dy;
+                if nx >= 0
+                    && nx < cells_w
+                    && ny >= 0
+                    && ny < cells_h
+                    && !visited[(ny * cells_w + nx) as usize]
+                {
+                    neighbors.push((nx, ny));
+                }
+            }
+
+            if neighbors.is_empty() {
+                // 行き止まり. 1つ戻る
+                stack.pop();
+            } else {
+                let roll = rng.roll_dice(1, neighbors.len() as i32) - 1;
+                let (nx, ny) = neighbors[roll as usize];
+                visited[(ny * cells_w + nx) as usize] = true;
+
+                // 今のセルと次のセルの間の壁を壊す
+                let wall_idx = self.map.xy_idx(cx + nx + 1, cy + ny + 1);
+                self.map.tiles[wall_idx] = TileType::Floor;
+                self.carve_cell(nx, ny);
+                stack.push((nx, ny));
+            }
+        }
+
+        self.starting_position = Position { x: 1, y: 1 };
+    }
+
+    fn get_map(&self) -> Map {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        self.starting_position
+    }
+
+    fn get_spawn_regions(&self) -> Vec<Vec<usize>> {
+        let start_idx = self
+            .map
+            .xy_idx(self.starting_position.x, self.starting_position.y);
+        grid_spawn_regions(&self.map, start_idx, SPAWN_CHUNK_SIZE)
+    }
+}
+
+impl MazeBuilder {
+    pub fn new(new_depth: i32, width: i32, height: i32) -> MazeBuilder {
+        MazeBuilder {
+            map: Map::new(new_depth, width, height),
+            starting_position: Position { x: 0, y: 0 },
+        }
+    }
+
+    fn carve_cell(&mut self, cx: i32, cy: i32) {
+        let idx = self.map.xy_idx(cx * 2 + 1, cy * 2 + 1);
+        self.map.tiles[idx] = TileType::Floor;
+    }
+}
@@ -0,0 +1,171 @@
+use super::{spawner, Map, Position, Rect, TileType, VisibilityMode};
+use rltk::RandomNumberGenerator;
+use std::str::FromStr;
+
+mod common;
+use common::*;
+mod bsp_rooms;
+use bsp_rooms::BspRoomsBuilder;
+mod cellular_automata;
+use cellular_automata::CellularAutomataBuilder;
+mod drunkard;
+use drunkard::DrunkardsWalkBuilder;
+mod maze;
+use maze::MazeBuilder;
+mod prefab;
+pub use prefab::{builtin_prefabs, Prefab};
+mod vaults;
+use vaults::VaultBuilder;
+mod terrain;
+use terrain::TerrainBuilder;
+
+/// マップ生成アルゴリズムの共通インターフェース
+/// build_mapで中身を彫ってから, 出来上がったMapと初期位置とspawn regionを取り出す
+pub trait MapBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator);
+    fn get_map(&self) -> Map;
+    fn get_starting_position(&self) -> Position;
+    /// monsterやitemを湧かせてよいタイルのまとまり. 1つのregionごとにspawnerが呼ばれる
+    fn get_spawn_regions(&self) -> Vec<Vec<usize>>;
+    /// 必ず出現させるentity. (タイルのindex, spawnerでの名前)
+    fn get_spawn_list(&self) -> Vec<(usize, String)> {
+        Vec::new()
+    }
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum BuilderKind {
+    Bsp,
+    Cellular,
+    Drunkard,
+    Maze,
+}
+
+impl FromStr for BuilderKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bsp" => Ok(BuilderKind::Bsp),
+            "cellular" => Ok(BuilderKind::Cellular),
+            "drunkard" => Ok(BuilderKind::Drunkard),
+            "maze" => Ok(BuilderKind::Maze),
+            _ => Err(format!(
+                "Unknown map builder: {} (expected bsp, cellular, drunkard or maze)",
+                s
+            )),
+        }
+    }
+}
+
+/// 指定のアルゴリズムでマップをつくって, その上にprefabを焼き付け, 地形を足すbuilderを返す
+pub fn builder_for(
+    kind: BuilderKind,
+    new_depth: i32,
+    width: i32,
+    height: i32,
+) -> Box<dyn MapBuilder> {
+    let base = base_builder(kind, new_depth, width, height);
+    let vaults = VaultBuilder::new(new_depth, width, height, base);
+    Box::new(TerrainBuilder::new(
+        new_depth,
+        width,
+        height,
+        Box::new(vaults),
+    ))
+}
+
+fn base_builder(kind: BuilderKind, new_depth: i32, width: i32, height: i32) -> Box<dyn MapBuilder> {
+    match kind {
+        BuilderKind::Bsp => Box::new(BspRoomsBuilder::new(new_depth, width, height)),
+        BuilderKind::Cellular => Box::new(CellularAutomataBuilder::new(new_depth, width, height)),
+        BuilderKind::Drunkard => Box::new(DrunkardsWalkBuilder::new(new_depth, width, height)),
+        BuilderKind::Maze => Box::new(MazeBuilder::new(new_depth, width, height)),
+    }
+}
+
+/// フロアごとにランダムにbuilderを選ぶ
+pub fn random_builder(
+    new_depth: i32,
+    width: i32,
+    height: i32,
+    rng: &mut RandomNumberGenerator,
+) -> Box<dyn MapBuilder> {
+    let kind = match rng.roll_dice(1, 4) {
+        1 => BuilderKind::Bsp,
+        2 => BuilderKind::Cellular,
+        3 => BuilderKind::Drunkard,
+        _ => BuilderKind::Maze,
+    };
+    builder_for(kind, new_depth, width, height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{MAPHEIGHT, MAPWIDTH};
+
+    // builderごとに何千個もつくって確かめる
+    const SEEDS: u64 = 2000;
+
+    // 広い洞窟がつくれない小さいマップでも, つくり直しを繰り返さずに終わる
+    #[test]
+    fn caves_build_on_tiny_maps() {
+        for (width, height) in [(12, 10), (5, 5), (3, 3)] {
+            for seed in 0..20 {
+                let mut rng = RandomNumberGenerator::seeded(seed);
+                let mut builder = CellularAutomataBuilder::new(1, width, height);
+                builder.build_map(&mut rng);
+                let map = builder.get_map();
+                let start = builder.get_starting_position();
+                let start_idx = map.xy_idx(start.x, start.y);
+                assert_eq!(map.tiles[start_idx], TileType::Floor);
+                assert!(is_fully_connected(&map, start_idx));
+            }
+        }
+    }
+
+    #[test]
+    fn every_floor_is_reachable_from_start() {
+        let kinds = [
+            BuilderKind::Bsp,
+            BuilderKind::Cellular,
+            BuilderKind::Drunkard,
+            BuilderKind::Maze,
+        ];
+        for kind in kinds.iter() {
+            for seed in 0..SEEDS {
+                let mut rng = RandomNumberGenerator::seeded(seed);
+                let mut builder = builder_for(*kind, 1, MAPWIDTH, MAPHEIGHT);
+                builder.build_map(&mut rng);
+
+                let map = builder.get_map();
+                let start = builder.get_starting_position();
+                let start_idx = map.xy_idx(start.x, start.y);
+                assert_eq!(
+                    map.tiles[start_idx],
+                    TileType::Floor,
+                    "{:?} seed {}: start is not on the floor",
+                    kind,
+                    seed
+                );
+                assert!(
+                    is_fully_connected(&map, start_idx),
+                    "{:?} seed {}: unreachable floor left",
+                    kind,
+                    seed
+                );
+                for (idx, name) in builder.get_spawn_list().iter() {
+                    assert_eq!(
+                        map.tiles[*idx],
+                        TileType::Floor,
+                        "{:?} seed {}: {} spawns inside a wall",
+                        kind,
+                        seed,
+                        name
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use super::{spawner, Map, TileType};
+use rltk::rex::XpFile;
+use rltk::RandomNumberGenerator;
+use std::fmt;
+
+/// REX Paintで描いた部屋や宝物庫
+/// glyphの対応:
+/// - `#`: 壁
+/// - `.`: 床
+/// - ` `(または空のセル): 元のマップをそのまま残す
+/// - `g`, `o`: Goblin, Orc
+/// - `!`: Red Potion
+/// - `m`, `*`: ランダムなmonster, ランダムなitem
+///
+/// resources/のnyan.xpとmltest.xpはrltkのサンプルの絵(ねこと, レイヤーの重ね合わせの確認用)で,
+/// 上の対応表にない文字で描かれてるのでprefabとしては読み込めない. テストで確かめてある
+#[derive(Clone, Debug)]
+pub struct Prefab {
+    pub name: String,
+    pub width: i32,
+    pub height: i32,
+    // row-major. REX Paintはcolumn-majorなので読み込むときに並べ替える
+    cells: Vec<char>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PrefabError {
+    /// .xpファイルが読めなかった
+    Read { name: String, reason: String },
+    /// 上の対応表にない文字が描かれていた
+    UnknownGlyph {
+        name: String,
+        glyph: char,
+        x: i32,
+        y: i32,
+    },
+    /// (x, y)に置くとマップからはみ出す
+    OutOfBounds { name: String, x: i32, y: i32 },
+}
+
+impl fmt::Display for PrefabError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PrefabError::Read { name, reason } => {
+                write!(f, "Unable to read prefab {}: {}", name, reason)
+            }
+            PrefabError::UnknownGlyph { name, glyph, x, y } => write!(
+                f,
+                "Prefab {} has unknown glyph '{}' at ({}, {})",
+                name, glyph, x, y
+            ),
+            PrefabError::OutOfBounds { name, x, y } => {
+                write!(f, "Prefab {} does not fit the map at ({}, {})", name, x, y)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PrefabError {}
+
+const TREASURE_VAULT: &[u8] = include_bytes!("../../resources/prefabs/treasure_vault.xp");
+const PILLAR_HALL: &[u8] = include_bytes!("../../resources/prefabs/pillar_hall.xp");
+const GOBLIN_CAMP: &[u8] = include_bytes!("../../resources/prefabs/goblin_camp.xp");
+
+/// ゲームに同梱しているprefabをぜんぶ読み込む
+pub fn builtin_prefabs() -> Vec<Prefab> {
+    [
+        ("treasure_vault", TREASURE_VAULT),
+        ("pillar_hall", PILLAR_HALL),
+        ("goblin_camp", GOBLIN_CAMP),
+    ]
+    .iter()
+    .map(|(name, bytes)| Prefab::from_xp_bytes(name, bytes).expect("Bundled prefab is broken"))
+    .collect()
+}
+
+impl Prefab {
+    pub fn from_xp_bytes(name: &str, bytes: &[u8]) -> Result<Prefab, PrefabError> {
+        let mut reader = bytes;
+        let xp = XpFile::read(&mut reader).map_err(|e| PrefabError::Read {
+            name: name.to_string(),
+            reason: e.to_string(),
+        })?;
+        Prefab::from_xp(name, &xp)
+    }
+
+    pub fn from_xp(name: &str, xp: &XpFile) -> Result<Prefab, PrefabError> {
+        // 1枚目のレイヤーだけ使う
+        let layer = xp.layers.first().ok_or_else(|| PrefabError::Read {
+            name: name.to_string(),
+            reason: "no layers".to_string(),
+        })?;
+
+        let mut cells = Vec::with_capacity(layer.width * layer.height);
+        for y in 0..layer.height {
+            for x in 0..layer.width {
+                let cell = layer.get(x, y).unwrap();
+                let glyph = if cell.ch == 0 {
+                    ' '
+                } else {
+                    rltk::to_char(cell.ch as u8)
+                };
+                if !is_known_glyph(glyph) {
+                    return Err(PrefabError::UnknownGlyph {
+                        name: name.to_string(),
+                        glyph,
+                        x: x as i32,
+                        y: y as i32,
+                    });
+                }
+                cells.push(glyph);
+            }
+        }
+
+        Ok(Prefab {
+            name: name.to_string(),
+            width: layer.width as i32,
+            height: layer.height as i32,
+            cells,
+        })
+    }
+
+    /// 外周の壁を残したまま(x, y)に置けるか
+    pub fn fits(&self, map: &Map, x: i32, y: i32) -> bool {
+        x >= 1 && y >= 1 && x + self.width < map.width && y + self.height < map.height
+    }
+
+    /// (x, y)を左上にしてマップに焼き付ける
+    /// 出現させるべきentityを(タイルのindex, 名前)で返す
+    pub fn stamp(
+        &self,
+        map: &mut Map,
+        x: i32,
+        y: i32,
+        rng: &mut RandomNumberGenerator,
+    ) -> Result<Vec<(usize, String)>, PrefabError> {
+        if !self.fits(map, x, y) {
+            return Err(PrefabError::OutOfBounds {
+                name: self.name.clone(),
+                x,
+                y,
+            });
+        }
+
+        let mut spawns = Vec::new();
+        for py in 0..self.height {
+            for px in 0..self.width {
+                let glyph = self.cells[(py * self.width + px) as usize];
+                let idx = map.xy_idx(x + px, y + py);
+                match glyph {
+                    ' ' => {}
+                    '#' => map.tiles[idx] = TileType::Wall,
+                    _ => {
+                        map.tiles[idx] = TileType::Floor;
+                        let spawn = match glyph {
+                            'g' => Some("Goblin"),
+                            'o' => Some("Orc"),
+                            '!' => Some("Red Potion"),
+                            'm' => Some(spawner::random_monster_name(rng)),
+                            '*' => Some(spawner::random_item_name(rng)),
+                            _ => None,
+                        };
+                        if let Some(name) = spawn {
+                            spawns.push((idx, name.to_string()));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(spawns)
+    }
+}
+
+fn is_known_glyph(glyph: char) -> bool {
+    matches!(glyph, ' ' | '#' | '.' | 'g' | 'o' | '!' | 'm' | '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_prefab_stamps_its_spawns() {
+        let vault = builtin_prefabs()
+            .into_iter()
+            .find(|p| p.name == "treasure_vault")
+            .unwrap();
+        assert_eq!((vault.width, vault.height), (9, 7));
+
+        let mut map = Map::new(1, 20, 20);
+        let mut rng = RandomNumberGenerator::seeded(1);
+        let spawns = vault.stamp(&mut map, 1, 1, &mut rng).unwrap();
+        // Orcが2匹, Red Potionが4つ, ランダムなitemが1つ
+        assert_eq!(spawns.len(), 7);
+        assert_eq!(spawns.iter().filter(|(_, n)| n == "Orc").count(), 2);
+        assert_eq!(spawns.iter().filter(|(_, n)| n == "Red Potion").count(), 4);
+        assert_eq!(map.tiles[map.xy_idx(1, 1)], TileType::Wall);
+        assert_eq!(map.tiles[map.xy_idx(2, 2)], TileType::Floor);
+    }
+
+    #[test]
+    fn sample_images_are_not_prefabs() {
+        let nyan = Prefab::from_xp_bytes("nyan", include_bytes!("../../resources/nyan.xp"));
+        assert!(matches!(
+            nyan,
+            Err(PrefabError::UnknownGlyph { x: 0, y: 0, .. })
+        ));
+        let mltest = Prefab::from_xp_bytes("mltest", include_bytes!("../../resources/mltest.xp"));
+        assert_eq!(
+            mltest.unwrap_err(),
+            PrefabError::UnknownGlyph {
+                name: "mltest".to_string(),
+                glyph: 'A',
+                x: 0,
+                y: 0
+            }
+        );
+    }
+
+    #[test]
+    fn prefab_overhanging_the_map_is_rejected() {
+        let vault = builtin_prefabs().into_iter().next().unwrap();
+        let mut map = Map::new(1, 20, 20);
+        let mut rng = RandomNumberGenerator::seeded(1);
+        let before = map.tiles.clone();
+
+        for (x, y) in [
+            (0, 1),
+            (1, 0),
+            (20 - vault.width, 1),
+            (1, 20 - vault.height),
+        ] {
+            assert!(!vault.fits(&map, x, y));
+            assert_eq!(
+                vault.stamp(&mut map, x, y, &mut rng).unwrap_err(),
+                PrefabError::OutOfBounds {
+                    name: vault.name.clone(),
+                    x,
+                    y
+                }
+            );
+        }
+        // はみ出すときは何も書き換えない
+        assert_eq!(map.tiles, before);
+        assert!(vault.fits(&map, 19 - vault.width, 19 - vault.height));
+    }
+}
@@ -0,0 +1,189 @@
+use super::{is_fully_connected, Map, MapBuilder, Position, TileType};
+use rltk::{DistanceAlg, Point, RandomNumberGenerator};
+
+// 川の橋はだいたいこの行数おきに架ける
+const BRIDGE_SPACING: i32 = 6;
+const MAX_LAVA_POOLS: i32 = 2;
+// 初期位置からこれより近くには溶岩を置かない
+const LAVA_SAFE_DISTANCE: f32 = 6.0;
+const RUBBLE_PERCENT: i32 = 3;
+
+/// 別のbuilderがつくったマップに川や溶岩, 瓦礫などの地形を足す
+pub struct TerrainBuilder {
+    parent: Box<dyn MapBuilder>,
+    map: Map,
+    starting_position: Position,
+    spawn_list: Vec<(usize, String)>,
+}
+
+impl MapBuilder for TerrainBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        self.parent.build_map(rng);
+        self.map = self.parent.get_map();
+        self.starting_position = self.parent.get_starting_position();
+        self.spawn_list = self.parent.get_spawn_list();
+
+        if rng.roll_dice(1, 2) == 1 {
+            self.add_river(rng);
+        }
+        let num_pools = rng.roll_dice(1, MAX_LAVA_POOLS + 1) - 1;
+        for _ in 0..num_pools {
+            self.add_lava_pool(rng);
+        }
+        self.add_rubble(rng);
+
+        debug_assert!(is_fully_connected(&self.map, self.start_idx()));
+    }
+
+    fn get_map(&self) -> Map {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        self.starting_position
+    }
+
+    fn get_spawn_regions(&self) -> Vec<Vec<usize>> {
+        // 水や溶岩の上には湧かせない
+        self.parent
+            .get_spawn_regions()
+            .into_iter()
+            .map(|region| {
+                region
+                    .into_iter()
+                    .filter(|idx| self.map.tiles[*idx] == TileType::Floor)
+                    .collect::<Vec<usize>>()
+            })
+            .filter(|region| !region.is_empty())
+            .collect()
+    }
+
+    fn get_spawn_list(&self) -> Vec<(usize, String)> {
+        self.spawn_list.clone()
+    }
+}
+
+impl TerrainBuilder {
+    pub fn new(
+        new_depth: i32,
+        width: i32,
+        height: i32,
+        parent: Box<dyn MapBuilder>,
+    ) -> TerrainBuilder {
+        TerrainBuilder {
+            parent,
+            map: Map::new(new_depth, width, height),
+            starting_position: Position { x: 0, y: 0 },
+            spawn_list: Vec::new(),
+        }
+    }
+
+    fn start_idx(&self) -> usize {
+        self.map
+            .xy_idx(self.starting_position.x, self.starting_position.y)
+    }
+
+    // 初期位置やドアなど, spawnの予定があるタイルは地形を変えない
+    fn can_change(&self, idx: usize) -> bool {
+        self.map.tiles[idx] == TileType::Floor
+            && idx != self.start_idx()
+            && !self
+                .spawn_list
+                .iter()
+                .any(|(spawn_idx, _)| *spawn_idx == idx)
+    }
+
+    /// 上から下へ蛇行する川を流す. 床と交わるところだけ水になる
+    /// 真ん中の1マスが深い水, 両岸が浅い水で, ところどころに橋を架ける
+    fn add_river(&mut self, rng: &mut RandomNumberGenerator) {
+        let original_tiles = self.map.tiles.clone();
+        let mut x = rng.range(self.map.width / 4, self.map.width * 3 / 4);
+        // (川の行, 深い水のx)
+        let mut course: Vec<(i32, i32)> = Vec::new();
+
+        for y in 1..self.map.height - 1 {
+            let idx = self.map.xy_idx(x, y);
+            if self.can_change(idx) {
+                self.map.tiles[idx] = TileType::DeepWater;
+                course.push((y, x));
+            }
+            for bank_x in [x - 1, x + 1].iter() {
+                let bank_idx = self.map.xy_idx(*bank_x, y);
+                if self.can_change(bank_idx) {
+                    self.map.tiles[bank_idx] = TileType::ShallowWater;
+                }
+            }
+            x = i32::clamp(x + rng.range(-1, 2), 2, self.map.width - 3);
+        }
+
+        // 両岸が歩ける行にだけ橋を架けられる
+        let crossings: Vec<usize> = course
+            .iter()
+            .filter(|(y, x)| {
+                self.map.tiles[self.map.xy_idx(x - 1, *y)].is_walkable()
+                    && self.map.tiles[self.map.xy_idx(x + 1, *y)].is_walkable()
+            })
+            .map(|(y, x)| self.map.xy_idx(*x, *y))
+            .collect();
+
+        let offset = rng.range(0, BRIDGE_SPACING);
+        for (i, idx) in crossings.iter().enumerate() {
+            if i as i32 % BRIDGE_SPACING == offset {
+                self.map.tiles[*idx] = TileType::Bridge;
+            }
+        }
+
+        // まだ分断されてたら橋を足していく. それでもだめなら川はあきらめる
+        for idx in crossings.iter() {
+            if is_fully_connected(&self.map, self.start_idx()) {
+                return;
+            }
+            self.map.tiles[*idx] = TileType::Bridge;
+        }
+        if !is_fully_connected(&self.map, self.start_idx()) {
+            self.map.tiles = original_tiles;
+        }
+    }
+
+    /// 丸い溶岩だまりを置く. 溶岩は歩けるので分断はしない
+    fn add_lava_pool(&mut self, rng: &mut RandomNumberGenerator) {
+        let start = Point::new(self.starting_position.x, self.starting_position.y);
+        let cx = rng.range(1, self.map.width - 1);
+        let cy = rng.range(1, self.map.height - 1);
+        let center = Point::new(cx, cy);
+        if self.map.tiles[self.map.xy_idx(cx, cy)] != TileType::Floor
+            || DistanceAlg::Pythagoras.distance2d(start, center) < LAVA_SAFE_DISTANCE
+        {
+            return;
+        }
+
+        let radius = rng.range(1, 3);
+        for y in cy - radius..=cy + radius {
+            for x in cx - radius..=cx + radius {
+                if x < 1 || x > self.map.width - 2 || y < 1 || y > self.map.height - 2 {
+                    continue;
+                }
+                let idx = self.map.xy_idx(x, y);
+                if DistanceAlg::Pythagoras.distance2d(center, Point::new(x, y)) <= radius as f32
+                    && self.can_change(idx)
+                {
+                    self.map.tiles[idx] = TileType::Lava;
+                }
+            }
+        }
+    }
+
+    /// 部屋の外の床に瓦礫を散らす
+    fn add_rubble(&mut self, rng: &mut RandomNumberGenerator) {
+        for idx in 0..self.map.tiles.len() {
+            let x = idx as i32 % self.map.width;
+            let y = idx as i32 / self.map.width;
+            if self.map.room_at(x, y).is_none()
+                && self.can_change(idx)
+                && rng.roll_dice(1, 100) <= RUBBLE_PERCENT
+            {
+                self.map.tiles[idx] = TileType::Rubble;
+            }
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use super::{
+    builtin_prefabs, cull_unreachable, is_fully_connected, reachable_from, Map, MapBuilder,
+    Position, Prefab, Rect, TileType,
+};
+use rltk::RandomNumberGenerator;
+
+const MAX_VAULTS: i32 = 2;
+const PLACEMENT_ATTEMPTS: i32 = 20;
+
+/// 別のbuilderがつくったマップの上にprefabを焼き付ける
+pub struct VaultBuilder {
+    parent: Box<dyn MapBuilder>,
+    prefabs: Vec<Prefab>,
+    map: Map,
+    starting_position: Position,
+    spawn_list: Vec<(usize, String)>,
+    // prefabを置いた場所. ここにはspawn regionから湧かせない
+    vaults: Vec<Rect>,
+}
+
+impl MapBuilder for VaultBuilder {
+    fn build_map(&mut self, rng: &mut RandomNumberGenerator) {
+        self.parent.build_map(rng);
+        self.map = self.parent.get_map();
+        self.starting_position = self.parent.get_starting_position();
+        self.spawn_list = self.parent.get_spawn_list();
+
+        if self.prefabs.is_empty() {
+            return;
+        }
+
+        let num_vaults = rng.roll_dice(1, MAX_VAULTS + 1) - 1;
+        for _ in 0..num_vaults {
+            let roll = rng.roll_dice(1, self.prefabs.len() as i32) - 1;
+            let prefab = self.prefabs[roll as usize].clone();
+            self.place_vault(&prefab, rng);
+        }
+
+        // prefabの中の行き止まりなど, 初期位置から行けない床は埋める
+        // そこに予定されていたspawnも取り消す
+        let start_idx = self
+            .map
+            .xy_idx(self.starting_position.x, self.starting_position.y);
+        cull_unreachable(&mut self.map, start_idx);
+        debug_assert!(is_fully_connected(&self.map, start_idx));
+        let tiles = &self.map.tiles;
+        self.spawn_list
+            .retain(|(idx, _)| tiles[*idx] == TileType::Floor);
+    }
+
+    fn get_map(&self) -> Map {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        self.starting_position
+    }
+
+    fn get_spawn_regions(&self) -> Vec<Vec<usize>> {
+        let width = self.map.width;
+        self.parent
+            .get_spawn_regions()
+            .into_iter()
+            .map(|region| {
+                region
+                    .into_iter()
+                    .filter(|idx| {
+                        let x = *idx as i32 % width;
+                        let y = *idx as i32 / width;
+                        self.map.tiles[*idx] == TileType::Floor
+                            && !self.vaults.iter().any(|v| contains(v, x, y))
+                    })
+                    .collect::<Vec<usize>>()
+            })
+            .filter(|region| !region.is_empty())
+            .collect()
+    }
+
+    fn get_spawn_list(&self) -> Vec<(usize, String)> {
+        self.spawn_list.clone()
+    }
+}
+
+impl VaultBuilder {
+    pub fn new(
+        new_depth: i32,
+        width: i32,
+        height: i32,
+        parent: Box<dyn MapBuilder>,
+    ) -> VaultBuilder {
+        VaultBuilder {
+            parent,
+            prefabs: builtin_prefabs(),
+            map: Map::new(new_depth, width, height),
+            starting_position: Position { x: 0, y: 0 },
+            spawn_list: Vec::new(),
+            vaults: Vec::new(),
+        }
+    }
+
+    // 置けそうな場所を何回か探して, 見つかったら焼き付ける
+    fn place_vault(&mut self, prefab: &Prefab, rng: &mut RandomNumberGenerator) {
+        let max_x = self.map.width - 1 - prefab.width;
+        let max_y = self.map.height - 1 - prefab.height;
+
+        for _ in 0..PLACEMENT_ATTEMPTS {
+            let x = rng.range(1, i32::max(2, max_x + 1));
+            let y = rng.range(1, i32::max(2, max_y + 1));
+            let area = Rect::new(x, y, prefab.width - 1, prefab.height - 1);
+
+            // 初期位置や他のprefabとは重ねない. 真ん中が床なら周りとつながりやすい
+            let (cx, cy) = area.center();
+            let center_idx = self.map.xy_idx(cx, cy);
+            if contains(&area, self.starting_position.x, self.starting_position.y)
+                || self.vaults.iter().any(|v| v.intersect(&area))
+                || self.map.tiles[center_idx] != TileType::Floor
+            {
+                continue;
+            }
+
+            // 試しに焼き付けてみて, prefabの外の床が分断されてしまうなら別の場所を探す
+            let mut stamped = self.map.clone();
+            match prefab.stamp(&mut stamped, x, y, rng) {
+                Ok(spawns) => {
+                    if !self.keeps_connectivity(&stamped, &area) {
+                        continue;
+                    }
+                    self.map = stamped;
+                    // 上書きされたタイルに予定されていたspawnは取り消す
+                    let vault = area;
+                    let width = self.map.width;
+                    self.spawn_list.retain(|(idx, _)| {
+                        !contains(&vault, *idx as i32 % width, *idx as i32 / width)
+                    });
+                    self.spawn_list.extend(spawns);
+                    self.vaults.push(vault);
+                }
+                Err(e) => rltk::console::log(e.to_string()),
+            }
+            return;
+        }
+    }
+
+    // 焼き付ける前に初期位置から行けた床は, prefabの外なら焼き付けた後も行けるか
+    fn keeps_connectivity(&self, stamped: &Map, area: &Rect) -> bool {
+        let start_idx = self
+            .map
+            .xy_idx(self.starting_position.x, self.starting_position.y);
+        let before = reachable_from(&self.map, start_idx);
+        let after = reachable_from(stamped, start_idx);
+        let width = self.map.width;
+        before.iter().enumerate().all(|(idx, was_reachable)| {
+            !*was_reachable || after[idx] || contains(area, idx as i32 % width, idx as i32 / width)
+        })
+    }
+}
+
+// Rect::newは幅と高さを受け取るので, x2, y2もRectに含まれる
+fn contains(rect: &Rect, x: i32, y: i32) -> bool {
+    x >= rect.x1 && x <= rect.x2 && y >= rect.y1 && y <= rect.y2
+}
@@ -1,33 +1,276 @@
-use super::{BlocksTile, Map, Position};
+use super::{BlocksTile, BlocksVisibility, Map, Position};
 use specs::prelude::*;
+use specs::storage::ComponentEvent;
+
+// 毎ターン全部のentityを入れ直すのは大きいマップだと重いので,
+// Position, BlocksTile, BlocksVisibilityが変わったentityだけ入れ直す
+
+/// 変わったentityを知るためのreader. コンポーネントを登録したすぐあとにつくる
+pub struct IndexReaders {
+    positions: ReaderId<ComponentEvent>,
+    blocks_tile: ReaderId<ComponentEvent>,
+    blocks_visibility: ReaderId<ComponentEvent>,
+}
+
+impl IndexReaders {
+    pub fn new(ecs: &World) -> IndexReaders {
+        IndexReaders {
+            positions: ecs.write_storage::<Position>().register_reader(),
+            blocks_tile: ecs.write_storage::<BlocksTile>().register_reader(),
+            blocks_visibility: ecs.write_storage::<BlocksVisibility>().register_reader(),
+        }
+    }
+}
+
+fn collect_changes<T>(
+    storage: &ReadStorage<T>,
+    reader: &mut ReaderId<ComponentEvent>,
+    dirty: &mut BitSet,
+) where
+    T: Component,
+    T::Storage: Tracked,
+{
+    for event in storage.channel().read(reader) {
+        match event {
+            ComponentEvent::Inserted(id)
+            | ComponentEvent::Modified(id)
+            | ComponentEvent::Removed(id) => {
+                dirty.add(*id);
+            }
+        }
+    }
+}
+
+/// 全部のentityを入れ直す. 新しいマップになったときと, 比べるためのテスト用
+pub fn rebuild_index(
+    map: &mut Map,
+    entities: &Entities,
+    position: &ReadStorage<Position>,
+    blockers: &ReadStorage<BlocksTile>,
+    view_blockers: &ReadStorage<BlocksVisibility>,
+) {
+    map.clear_index();
+    for (entity, position) in (entities, position).join() {
+        let idx = map.xy_idx(position.x, position.y);
+        map.index_entity(
+            entity,
+            idx,
+            blockers.contains(entity),
+            view_blockers.contains(entity),
+        );
+    }
+    map.index_built = true;
+}
 
 pub struct MapIndexingSystem {}
 
 impl<'a> System<'a> for MapIndexingSystem {
     type SystemData = (
         WriteExpect<'a, Map>,
+        WriteExpect<'a, IndexReaders>,
         ReadStorage<'a, Position>,
         ReadStorage<'a, BlocksTile>,
+        ReadStorage<'a, BlocksVisibility>,
         Entities<'a>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (mut map, position, blockers, entities) = data;
+        let (mut map, mut readers, position, blockers, view_blockers, entities) = data;
 
-        map.populate_blocked();
-        map.clear_content_index();
-        for (entity, position) in (&entities, &position).join() {
-            let idx = map.xy_idx(position.x, position.y);
+        let mut dirty = BitSet::new();
+        collect_changes(&position, &mut readers.positions, &mut dirty);
+        collect_changes(&blockers, &mut readers.blocks_tile, &mut dirty);
+        collect_changes(&view_blockers, &mut readers.blocks_visibility, &mut dirty);
 
-            // 障害物だったら,そのタイルのblockedをtrueにする
-            let _p: Option<&BlocksTile> = blockers.get(entity);
-            if let Some(_p) = _p {
-                map.blocked[idx] = true;
+        // 新しいフロアのマップはまだ空なので全部入れる. たまってた知らせは捨てていい
+        if !map.index_built {
+            rebuild_index(&mut map, &entities, &position, &blockers, &view_blockers);
+            return;
+        }
+
+        for id in (&dirty).join() {
+            // 消されたentityは番号しか残ってないので, まず番号で出してから今の姿で入れ直す
+            map.unindex_id(id);
+            let entity = entities.entity(id);
+            if !entities.is_alive(entity) {
+                continue;
+            }
+            if let Some(position) = position.get(entity) {
+                let idx = map.xy_idx(position.x, position.y);
+                map.index_entity(
+                    entity,
+                    idx,
+                    blockers.contains(entity),
+                    view_blockers.contains(entity),
+                );
             }
+        }
+    }
+}
 
-            // tile_contentの対応するindexにentityをメモっておく
-            // Copyだからcloneする必要はない. ECSからmoveしたくない
-            map.tile_content[idx].push(entity);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::TileType;
+    use std::time::{Duration, Instant};
+
+    fn new_world(width: i32, height: i32) -> World {
+        let mut ecs = World::new();
+        ecs.register::<Position>();
+        ecs.register::<BlocksTile>();
+        ecs.register::<BlocksVisibility>();
+        let mut map = Map::new(1, width, height);
+        for tile in map.tiles.iter_mut() {
+            *tile = TileType::Floor;
+        }
+        // 端を壁にして, 地形のblockedも混ざるようにする
+        for x in 0..width {
+            let idx = map.xy_idx(x, 0);
+            map.tiles[idx] = TileType::Wall;
         }
+        ecs.insert(map);
+        let readers = IndexReaders::new(&ecs);
+        ecs.insert(readers);
+        ecs
+    }
+
+    fn spawn(ecs: &mut World, rng: &mut rltk::RandomNumberGenerator) -> Entity {
+        let (width, height) = {
+            let map = ecs.fetch::<Map>();
+            (map.width, map.height)
+        };
+        let mut builder = ecs.create_entity().with(Position {
+            x: rng.range(0, width),
+            y: rng.range(0, height),
+        });
+        if rng.roll_dice(1, 2) == 1 {
+            builder = builder.with(BlocksTile {});
+        }
+        if rng.roll_dice(1, 4) == 1 {
+            builder = builder.with(BlocksVisibility {});
+        }
+        builder.build()
+    }
+
+    // いまのworldから全部入れ直したマップと比べる
+    fn assert_matches_rebuild(ecs: &World) {
+        let map = ecs.fetch::<Map>();
+        let mut rebuilt = Map::clone(&map);
+        rebuild_index(
+            &mut rebuilt,
+            &ecs.entities(),
+            &ecs.read_storage(),
+            &ecs.read_storage(),
+            &ecs.read_storage(),
+        );
+        assert_eq!(map.blocked, rebuilt.blocked);
+        assert_eq!(map.view_blocked, rebuilt.view_blocked);
+        for (idx, content) in map.tile_content.iter().enumerate() {
+            let mut content = content.clone();
+            let mut expected = rebuilt.tile_content[idx].clone();
+            content.sort();
+            expected.sort();
+            assert_eq!(content, expected, "tile {}", idx);
+        }
+    }
+
+    #[test]
+    fn incremental_index_matches_full_rebuild() {
+        let mut ecs = new_world(30, 20);
+        let mut rng = rltk::RandomNumberGenerator::seeded(7);
+        let mut alive: Vec<Entity> = (0..60).map(|_| spawn(&mut ecs, &mut rng)).collect();
+        MapIndexingSystem {}.run_now(&ecs);
+        assert_matches_rebuild(&ecs);
+
+        for _ in 0..50 {
+            for _ in 0..10 {
+                let target = alive[rng.range(0, alive.len() as i32) as usize];
+                match rng.roll_dice(1, 6) {
+                    1 => {
+                        ecs.delete_entity(target).unwrap();
+                        alive.retain(|e| *e != target);
+                    }
+                    2 => alive.push(spawn(&mut ecs, &mut rng)),
+                    3 => {
+                        // ドアの開け閉めみたいに, ふさぐかどうかだけ変わる
+                        let mut blockers = ecs.write_storage::<BlocksTile>();
+                        if blockers.remove(target).is_none() {
+                            blockers.insert(target, BlocksTile {}).unwrap();
+                        }
+                    }
+                    _ => {
+                        let mut positions = ecs.write_storage::<Position>();
+                        let pos = positions.get_mut(target).unwrap();
+                        pos.x = rng.range(0, 30);
+                        pos.y = rng.range(0, 20);
+                    }
+                }
+            }
+            // 遅れて消すentityも混ぜる
+            if rng.roll_dice(1, 3) == 1 {
+                let target = alive.pop().unwrap();
+                ecs.entities().delete(target).unwrap();
+            }
+            ecs.maintain();
+            MapIndexingSystem {}.run_now(&ecs);
+            assert_matches_rebuild(&ecs);
+        }
+    }
+
+    // cargo make bench で動かす. 大きいマップで, 毎ターン一部のentityだけ動く場合
+    #[test]
+    #[ignore]
+    fn bench_incremental_index_vs_full_rebuild() {
+        const WIDTH: i32 = 500;
+        const HEIGHT: i32 = 500;
+        const ENTITIES: usize = 20_000;
+        const MOVES_PER_TURN: usize = 100;
+        const TURNS: usize = 200;
+
+        let mut ecs = new_world(WIDTH, HEIGHT);
+        let mut rng = rltk::RandomNumberGenerator::seeded(1);
+        let entities: Vec<Entity> = (0..ENTITIES).map(|_| spawn(&mut ecs, &mut rng)).collect();
+        MapIndexingSystem {}.run_now(&ecs);
+
+        let mut incremental = Duration::ZERO;
+        let mut full = Duration::ZERO;
+        for _ in 0..TURNS {
+            {
+                let mut positions = ecs.write_storage::<Position>();
+                for _ in 0..MOVES_PER_TURN {
+                    let target = entities[rng.range(0, ENTITIES as i32) as usize];
+                    let pos = positions.get_mut(target).unwrap();
+                    pos.x = (pos.x + rng.range(-1, 2)).clamp(0, WIDTH - 1);
+                    pos.y = (pos.y + rng.range(-1, 2)).clamp(0, HEIGHT - 1);
+                }
+            }
+
+            let start = Instant::now();
+            MapIndexingSystem {}.run_now(&ecs);
+            incremental += start.elapsed();
+
+            let mut map = Map::clone(&ecs.fetch::<Map>());
+            let start = Instant::now();
+            rebuild_index(
+                &mut map,
+                &ecs.entities(),
+                &ecs.read_storage(),
+                &ecs.read_storage(),
+                &ecs.read_storage(),
+            );
+            full += start.elapsed();
+        }
+        assert_matches_rebuild(&ecs);
+
+        println!(
+            "{}x{} map, {} entities, {} moves per turn, {} turns",
+            WIDTH, HEIGHT, ENTITIES, MOVES_PER_TURN, TURNS
+        );
+        println!("full rebuild: {:?} per turn", full / TURNS as u32);
+        println!(
+            "incremental:  {:?} per turn ({:.1}x faster)",
+            incremental / TURNS as u32,
+            full.as_secs_f64() / incremental.as_secs_f64()
+        );
     }
 }
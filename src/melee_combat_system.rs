@@ -16,7 +16,7 @@ impl<'a> System<'a> for MeleeCombatSystem {
     fn run(&mut self, data: Self::SystemData) {
         let (entities, mut log, mut wants_melee, names, combat_stats, mut inflict_damage) = data;
 
-        for (_entity, wants_melee, name, stats) in
+        for (entity, wants_melee, name, stats) in
             (&entities, &wants_melee, &names, &combat_stats).join()
         {
             if stats.hp > 0 {
@@ -36,7 +36,12 @@ impl<'a> System<'a> for MeleeCombatSystem {
                             "{} did {} damage to {}.",
                             &name.name, damage, &target_name.name
                         ));
-                        SufferDamage::new_damage(&mut inflict_damage, wants_melee.target, damage);
+                        SufferDamage::new_damage(
+                            &mut inflict_damage,
+                            wants_melee.target,
+                            damage,
+                            Some(entity),
+                        );
                     }
                 }
             }
@@ -1,7 +1,53 @@
-use super::{Confusion, Map, Monster, Position, RunState, Viewshed, WantsToMelee};
+use super::{
+    Confusion, EnteredTile, Map, Monster, Position, RunState, Swimmer, SwimmingMap, Viewshed,
+    WantsToMelee,
+};
 use rltk::Point;
 use specs::prelude::*;
 
+/// monsterがこのターンにすること
+#[derive(PartialEq, Debug)]
+pub enum MonsterAction {
+    Wait,
+    Attack,
+    // playerまでの道. 最初は今いるマスで, 次のマスに動く
+    Move(Vec<usize>),
+}
+
+/// monsterがこのターンに何をするか決める. 何も書き換えないので, デバッグ表示からも呼べる
+/// 混乱してたら動けない. 隣にplayerがいたら殴る. playerが見えてたら近づく. 泳げるなら深い水も通る
+pub fn choose_action(
+    map: &Map,
+    pos: Position,
+    viewshed: &Viewshed,
+    player_pos: Point,
+    confused: bool,
+    swimmer: bool,
+) -> MonsterAction {
+    if confused {
+        return MonsterAction::Wait;
+    }
+    let distance = rltk::DistanceAlg::Pythagoras.distance2d(Point::new(pos.x, pos.y), player_pos);
+    if distance < 1.5 {
+        return MonsterAction::Attack;
+    }
+    if !viewshed.visible_tiles.contains(&player_pos) {
+        return MonsterAction::Wait;
+    }
+    let start = map.xy_idx(pos.x, pos.y);
+    let end = map.xy_idx(player_pos.x, player_pos.y);
+    let path = if swimmer {
+        rltk::a_star_search(start, end, &SwimmingMap(map))
+    } else {
+        rltk::a_star_search(start, end, map)
+    };
+    if path.success && path.steps.len() > 1 {
+        MonsterAction::Move(path.steps)
+    } else {
+        MonsterAction::Wait
+    }
+}
+
 pub struct MonsterAI {}
 
 impl<'a> System<'a> for MonsterAI {
@@ -17,6 +63,8 @@ impl<'a> System<'a> for MonsterAI {
         WriteStorage<'a, Position>,
         WriteStorage<'a, WantsToMelee>,
         WriteStorage<'a, Confusion>,
+        WriteStorage<'a, EnteredTile>,
+        ReadStorage<'a, Swimmer>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
@@ -31,31 +79,42 @@ impl<'a> System<'a> for MonsterAI {
             mut position,
             mut wants_to_melee,
             mut confused,
+            mut entered_tile,
+            swimmers,
         ) = data;
 
         if *runstate != RunState::MonsterTurn {
             return;
         }
 
-        for (entity, mut viewshed, _monster, mut pos) in
-            (&entities, &mut viewshed, &monster, &mut position).join()
-        {
-            let mut can_act = true;
-
-            // 混乱してるなら動けない
-            let is_confused = confused.get_mut(entity);
-            if let Some(i_am_confused) = is_confused {
-                i_am_confused.turns -= 1;
-                if i_am_confused.turns < 1 {
-                    confused.remove(entity);
+        // 動かないmonsterのPositionまで変わったことにならないように, 読むだけにしておく
+        for (entity, viewshed, _monster) in (&entities, &mut viewshed, &monster).join() {
+            let pos = match position.get(entity) {
+                Some(pos) => *pos,
+                None => continue,
+            };
+            // 混乱してるなら動けない. 決めるのはこのターンの分を減らす前
+            let is_confused = match confused.get_mut(entity) {
+                Some(i_am_confused) => {
+                    i_am_confused.turns -= 1;
+                    if i_am_confused.turns < 1 {
+                        confused.remove(entity);
+                    }
+                    true
                 }
-                can_act = false;
-            }
+                None => false,
+            };
 
-            if can_act {
-                let distance =
-                    rltk::DistanceAlg::Pythagoras.distance2d(Point::new(pos.x, pos.y), *player_pos);
-                if distance < 1.5 {
+            match choose_action(
+                &map,
+                pos,
+                viewshed,
+                *player_pos,
+                is_confused,
+                swimmers.contains(entity),
+            ) {
+                MonsterAction::Wait => {}
+                MonsterAction::Attack => {
                     wants_to_melee
                         .insert(
                             entity,
@@ -64,24 +123,88 @@ impl<'a> System<'a> for MonsterAI {
                             },
                         )
                         .expect("Unable to insert attack");
-                } else if viewshed.visible_tiles.contains(&*player_pos) {
-                    // Path to the player
-                    let path = rltk::a_star_search(
-                        map.xy_idx(pos.x, pos.y),
-                        map.xy_idx(player_pos.x, player_pos.y),
-                        &mut *map,
-                    );
-                    if path.success && path.steps.len() > 1 {
-                        let mut idx = map.xy_idx(pos.x, pos.y);
-                        map.blocked[idx] = false;
-                        pos.x = path.steps[1] as i32 % map.width;
-                        pos.y = path.steps[1] as i32 / map.width;
-                        idx = map.xy_idx(pos.x, pos.y);
-                        map.blocked[idx] = true;
-                        viewshed.dirty = true;
+                }
+                MonsterAction::Move(path) => {
+                    // 次に動くmonsterが同じマスに来ないように, インデックスもすぐ動かす
+                    map.move_entity(entity, path[1]);
+                    if let Some(pos) = position.get_mut(entity) {
+                        pos.x = path[1] as i32 % map.width;
+                        pos.y = path[1] as i32 / map.width;
                     }
+                    viewshed.dirty = true;
+                    entered_tile
+                        .insert(entity, EnteredTile {})
+                        .expect("Unable to insert EnteredTile");
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TileType;
+
+    #[test]
+    fn confused_monsters_do_not_walk() {
+        let mut map = Map::new(1, 10, 3);
+        for x in 1..9 {
+            let idx = map.xy_idx(x, 1);
+            map.tiles[idx] = TileType::Floor;
+        }
+        map.populate_blocked();
+        let player_pos = Point::new(7, 1);
+        let viewshed = Viewshed {
+            visible_tiles: vec![player_pos],
+            range: 8,
+            dirty: false,
+        };
+        let pos = Position { x: 2, y: 1 };
+
+        let steps = (2..=7).map(|x| map.xy_idx(x, 1)).collect();
+        assert_eq!(
+            choose_action(&map, pos, &viewshed, player_pos, false, false),
+            MonsterAction::Move(steps)
+        );
+        assert_eq!(
+            choose_action(&map, pos, &viewshed, player_pos, true, false),
+            MonsterAction::Wait
+        );
+        let next_to = Position { x: 6, y: 1 };
+        assert_eq!(
+            choose_action(&map, next_to, &viewshed, player_pos, false, false),
+            MonsterAction::Attack
+        );
+    }
+
+    #[test]
+    fn only_swimmers_cross_deep_water() {
+        // 真ん中の列だけ深い水の川
+        let mut map = Map::new(1, 7, 3);
+        for x in 1..6 {
+            let idx = map.xy_idx(x, 1);
+            map.tiles[idx] = TileType::Floor;
+        }
+        let river = map.xy_idx(3, 1);
+        map.tiles[river] = TileType::DeepWater;
+        map.populate_blocked();
+        let player_pos = Point::new(5, 1);
+        let viewshed = Viewshed {
+            visible_tiles: vec![player_pos],
+            range: 8,
+            dirty: false,
+        };
+        let pos = Position { x: 1, y: 1 };
+
+        assert_eq!(
+            choose_action(&map, pos, &viewshed, player_pos, false, false),
+            MonsterAction::Wait
+        );
+        let steps = (1..=5).map(|x| map.xy_idx(x, 1)).collect();
+        assert_eq!(
+            choose_action(&map, pos, &viewshed, player_pos, false, true),
+            MonsterAction::Move(steps)
+        );
+    }
+}
@@ -0,0 +1,229 @@
+use super::{
+    config, describe, gamelog::GameLog, statistics::Statistics, tile_glyph, CombatStats,
+    InBackpack, Map, Name, Position, Renderable,
+};
+use specs::prelude::*;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MORGUE_DIR: &str = "morgue";
+// morgueファイルに残すログの数
+const MORGUE_LOG_LENGTH: usize = 50;
+
+#[derive(Debug)]
+pub enum MorgueError {
+    Io(String),
+    NoDataDir,
+}
+
+impl fmt::Display for MorgueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MorgueError::Io(e) => write!(f, "Unable to write morgue file: {}", e),
+            MorgueError::NoDataDir => write!(f, "No data directory for the morgue file"),
+        }
+    }
+}
+
+impl std::error::Error for MorgueError {}
+
+/// 1970-01-01からの秒をUTCの年月日と時分秒にする
+/// 日付の計算はhttp://howardhinnant.github.io/date_algorithms.html のcivil_from_days
+fn civil_from_unix(secs: u64) -> (i64, u32, u32, u32, u32, u32) {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (
+        year,
+        month,
+        day,
+        (rem / 3600) as u32,
+        (rem % 3600 / 60) as u32,
+        (rem % 60) as u32,
+    )
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// 今の日時. "2026-10-18 12:34:56 UTC"
+pub fn timestamp() -> String {
+    let (y, mo, d, h, mi, s) = civil_from_unix(unix_now());
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        y, mo, d, h, mi, s
+    )
+}
+
+/// 今日の日付. "2026-10-18"
+pub fn today() -> String {
+    let (y, mo, d, _, _, _) = civil_from_unix(unix_now());
+    format!("{:04}-{:02}-{:02}", y, mo, d)
+}
+
+/// 見たことのあるマスと, いま見えてるentityを文字で描く. 何も見てない端は削る
+fn map_dump(ecs: &World) -> Vec<String> {
+    let map = ecs.fetch::<Map>();
+    let player_entity = ecs.fetch::<Entity>();
+    let entities = ecs.entities();
+    let positions = ecs.read_storage::<Position>();
+    let renderables = ecs.read_storage::<Renderable>();
+
+    let mut glyphs: Vec<Option<char>> = map
+        .tiles
+        .iter()
+        .enumerate()
+        .map(|(idx, tile)| {
+            if map.revealed_tiles[idx] {
+                Some(rltk::to_char(tile_glyph(*tile).0 as u8))
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    // 画面と同じで, render_orderの小さいものが上に来るように後から描く
+    let mut data = (&entities, &positions, &renderables)
+        .join()
+        .collect::<Vec<_>>();
+    data.sort_by_key(|&a| std::cmp::Reverse(a.2.render_order));
+    for (entity, pos, render) in data.iter() {
+        let idx = map.xy_idx(pos.x, pos.y);
+        if map.visible_tiles[idx] || *entity == *player_entity {
+            glyphs[idx] = Some(rltk::to_char(render.glyph as u8));
+        }
+    }
+
+    let rows: Vec<i32> = (0..map.height)
+        .filter(|y| (0..map.width).any(|x| glyphs[map.xy_idx(x, *y)].is_some()))
+        .collect();
+    let columns: Vec<i32> = (0..map.width)
+        .filter(|x| (0..map.height).any(|y| glyphs[map.xy_idx(*x, y)].is_some()))
+        .collect();
+    let (Some(&left), Some(&right)) = (columns.first(), columns.last()) else {
+        return Vec::new();
+    };
+
+    rows.iter()
+        .map(|y| {
+            (left..=right)
+                .map(|x| glyphs[map.xy_idx(x, *y)].unwrap_or(' '))
+                .collect::<String>()
+                .trim_end()
+                .to_string()
+        })
+        .collect()
+}
+
+/// morgueファイルの中身
+pub fn morgue_text(ecs: &World) -> String {
+    let player_entity = *ecs.fetch::<Entity>();
+    let statistics = ecs.fetch::<Statistics>();
+    let depth = ecs.fetch::<Map>().depth;
+    let name = ecs
+        .read_storage::<Name>()
+        .get(player_entity)
+        .map_or("???".to_string(), |n| n.name.clone());
+
+    let mut lines = vec![
+        "風来のたぬぽん morgue file".to_string(),
+        format!("Written {}", timestamp()),
+        String::new(),
+        format!("{} died on depth {}.", name, depth),
+        statistics
+            .cause_of_death
+            .clone()
+            .unwrap_or_else(|| "Cause of death unknown".to_string()),
+        format!("Survived {} turns.", statistics.turns),
+        String::new(),
+        "== Final stats ==".to_string(),
+    ];
+    if let Some(stats) = ecs.read_storage::<CombatStats>().get(player_entity) {
+        lines.push(format!("HP: {} / {}", stats.hp, stats.max_hp));
+        lines.push(format!("Power: {}", stats.power));
+        lines.push(format!("Defense: {}", stats.defense));
+    }
+    lines.push(format!("Damage dealt: {}", statistics.damage_dealt));
+    lines.push(format!("Damage taken: {}", statistics.damage_taken));
+
+    // 同じmonsterはまとめて, 最初に倒した順に並べる
+    lines.push(String::new());
+    lines.push(format!("== Kills ({}) ==", statistics.kill_list.len()));
+    let mut kills: Vec<(&str, usize)> = Vec::new();
+    for victim in statistics.kill_list.iter() {
+        match kills.iter_mut().find(|(name, _)| *name == victim.as_str()) {
+            Some((_, count)) => *count += 1,
+            None => kills.push((victim.as_str(), 1)),
+        }
+    }
+    for (victim, count) in kills.iter() {
+        lines.push(format!("{:3} x {}", count, victim));
+    }
+
+    lines.push(String::new());
+    lines.push("== Inventory ==".to_string());
+    let entities = ecs.entities();
+    let backpack = ecs.read_storage::<InBackpack>();
+    let mut inventory: Vec<(usize, Entity)> = (&entities, &backpack)
+        .join()
+        .filter(|(_, pack)| pack.owner == player_entity)
+        .map(|(entity, pack)| (pack.slot, entity))
+        .collect();
+    inventory.sort_by_key(|(slot, _)| *slot);
+    for (slot, item) in inventory.iter() {
+        let letter = (b'a' + *slot as u8) as char;
+        lines.push(format!("{}) {}", letter, describe::item_name(ecs, *item)));
+    }
+
+    lines.push(String::new());
+    lines.push("== Map ==".to_string());
+    lines.extend(map_dump(ecs));
+
+    lines.push(String::new());
+    lines.push("== Last messages ==".to_string());
+    let log = ecs.fetch::<GameLog>();
+    let skip = log.entries.len().saturating_sub(MORGUE_LOG_LENGTH);
+    lines.extend(log.entries.iter().skip(skip).cloned());
+
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+/// ユーザーのデータディレクトリにmorgueファイルを書いて, その場所を返す
+pub fn write_morgue(ecs: &World) -> Result<PathBuf, MorgueError> {
+    let dir = config::data_file(MORGUE_DIR).ok_or(MorgueError::NoDataDir)?;
+    fs::create_dir_all(&dir).map_err(|e| MorgueError::Io(e.to_string()))?;
+    let (y, mo, d, h, mi, s) = civil_from_unix(unix_now());
+    let path = dir.join(format!(
+        "morgue-{:04}{:02}{:02}-{:02}{:02}{:02}.txt",
+        y, mo, d, h, mi, s
+    ));
+    fs::write(&path, morgue_text(ecs)).map_err(|e| MorgueError::Io(e.to_string()))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unix_time_to_civil_date() {
+        assert_eq!(civil_from_unix(0), (1970, 1, 1, 0, 0, 0));
+        // 2024-02-29 12:34:56 UTC
+        assert_eq!(civil_from_unix(1709210096), (2024, 2, 29, 12, 34, 56));
+    }
+}
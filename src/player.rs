@@ -1,19 +1,49 @@
 use super::{
-    gamelog::GameLog, CombatStats, Item, Map, Player, Point, Position, RunState, State, Viewshed,
-    WantsToMelee, WantsToPickupItem,
+    describe,
+    gamelog::GameLog,
+    inventory_system::{auto_pickup_items, items_at},
+    keymap::{Command, Keymap},
+    BlocksTile, BlocksVisibility, CombatStats, Door, EnteredTile, Map, Player, Point, Position,
+    Renderable, RunState, State, Swimmer, Viewshed, WantsToMelee, WantsToPickupItem,
 };
-use rltk::{Rltk, VirtualKeyCode};
+use rltk::Rltk;
 use specs::prelude::*;
 
-pub fn try_move_player(delta_x: i32, delta_y: i32, ecs: &mut World) {
+/// auto_pickupがfalseなら, 自動で拾う設定でも足元のアイテムを拾わない
+pub fn try_move_player(delta_x: i32, delta_y: i32, ecs: &mut World, auto_pickup: bool) {
+    let start = *ecs.fetch::<Point>();
+    // 閉じたドアにぶつかったら, 移動するかわりにドアを開ける
+    let door_to_open = move_or_find_door(delta_x, delta_y, ecs);
+    if let Some(door) = door_to_open {
+        set_door_open(ecs, door, true);
+        ecs.fetch_mut::<GameLog>()
+            .entries
+            .push("You open the door.".to_string());
+    }
+    if *ecs.fetch::<Point>() != start {
+        let player_pos = *ecs.fetch::<Point>();
+        let picked = if auto_pickup {
+            auto_pickup_items(ecs, player_pos)
+        } else {
+            Vec::new()
+        };
+        pickup_items(ecs, &picked);
+        report_items_here(ecs, &picked);
+    }
+}
+
+fn move_or_find_door(delta_x: i32, delta_y: i32, ecs: &mut World) -> Option<Entity> {
     let mut positions = ecs.write_storage::<Position>();
     let mut players = ecs.write_storage::<Player>();
     let mut viewsheds = ecs.write_storage::<Viewshed>();
     let combat_stats = ecs.read_storage::<CombatStats>();
+    let doors = ecs.read_storage::<Door>();
+    let swimmers = ecs.read_storage::<Swimmer>();
     let map = ecs.fetch::<Map>();
 
     let entities = ecs.entities();
     let mut wants_to_melee = ecs.write_storage::<WantsToMelee>();
+    let mut entered_tile = ecs.write_storage::<EnteredTile>();
 
     for (entity, _player, pos, viewshed) in
         (&entities, &mut players, &mut positions, &mut viewsheds).join()
@@ -23,7 +53,7 @@ pub fn try_move_player(delta_x: i32, delta_y: i32, ecs: &mut World) {
             || pos.y + delta_y < 1
             || pos.y + delta_y > map.height - 1
         {
-            return;
+            return None;
         }
 
         let destination_idx = map.xy_idx(pos.x + delta_x, pos.y + delta_y);
@@ -41,98 +71,311 @@ pub fn try_move_player(delta_x: i32, delta_y: i32, ecs: &mut World) {
                         },
                     )
                     .expect("Add target failed");
-                return;
+                return None;
+            }
+
+            if let Some(door) = doors.get(*potential_target) {
+                if !door.open {
+                    return Some(*potential_target);
+                }
             }
         }
 
-        if !map.blocked[destination_idx] {
-            pos.x = (pos.x + delta_x).clamp(0, 79);
-            pos.y = (pos.y + delta_y).clamp(0, 49);
+        if map.can_enter(destination_idx, swimmers.contains(entity)) {
+            pos.x += delta_x;
+            pos.y += delta_y;
 
             // @くんが移動したら視界が変わるからdirtyふらぐもtrueにする
             viewshed.dirty = true;
+            entered_tile
+                .insert(entity, EnteredTile {})
+                .expect("Unable to insert EnteredTile");
 
             let mut ppos = ecs.write_resource::<Point>();
             ppos.x = pos.x;
             ppos.y = pos.y;
         }
     }
+    None
+}
+
+/// ドアを開け閉めする
+/// 見通しが変わるので, インデックスのドアをすぐ入れ直してみんなの視界を計算し直させる
+fn set_door_open(ecs: &mut World, door_entity: Entity, open: bool) {
+    let mut doors = ecs.write_storage::<Door>();
+    let mut blocks_visibility = ecs.write_storage::<BlocksVisibility>();
+    let mut blocks_movement = ecs.write_storage::<BlocksTile>();
+    let mut renderables = ecs.write_storage::<Renderable>();
+    let mut viewsheds = ecs.write_storage::<Viewshed>();
+    let positions = ecs.read_storage::<Position>();
+    let mut map = ecs.fetch_mut::<Map>();
+
+    if let Some(door) = doors.get_mut(door_entity) {
+        door.open = open;
+    }
+    if open {
+        blocks_visibility.remove(door_entity);
+        blocks_movement.remove(door_entity);
+    } else {
+        blocks_visibility
+            .insert(door_entity, BlocksVisibility {})
+            .expect("Unable to insert BlocksVisibility");
+        blocks_movement
+            .insert(door_entity, BlocksTile {})
+            .expect("Unable to insert BlocksTile");
+    }
+    if let Some(render) = renderables.get_mut(door_entity) {
+        render.glyph = rltk::to_cp437(if open { '\'' } else { '+' });
+    }
+    if let Some(pos) = positions.get(door_entity) {
+        let idx = map.xy_idx(pos.x, pos.y);
+        map.index_entity(door_entity, idx, !open, !open);
+    }
+
+    for viewshed in (&mut viewsheds).join() {
+        viewshed.dirty = true;
+    }
+}
+
+/// 隣にある開いたドアを閉める. 何かが挟まってるドアは閉められない
+/// 1つでも閉めたらtrue
+fn close_adjacent_doors(ecs: &mut World) -> bool {
+    let mut to_close: Vec<Entity> = Vec::new();
+    // 開いたドアはあるけど, 何かが挟まってる
+    let mut obstructed = false;
+    {
+        let player_pos = ecs.fetch::<Point>();
+        let map = ecs.fetch::<Map>();
+        let doors = ecs.read_storage::<Door>();
+
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let x = player_pos.x + dx;
+                let y = player_pos.y + dy;
+                if (dx == 0 && dy == 0) || x < 0 || x >= map.width || y < 0 || y >= map.height {
+                    continue;
+                }
+                let content = &map.tile_content[map.xy_idx(x, y)];
+                let open_door = content
+                    .iter()
+                    .find(|e| doors.get(**e).is_some_and(|door| door.open));
+                if let Some(door) = open_door {
+                    if content.len() == 1 {
+                        to_close.push(*door);
+                    } else {
+                        obstructed = true;
+                    }
+                }
+            }
+        }
+    }
+
+    for door in to_close.iter() {
+        set_door_open(ecs, *door, false);
+    }
+
+    let mut gamelog = ecs.fetch_mut::<GameLog>();
+    if to_close.is_empty() {
+        let message = if obstructed {
+            "Something is in the way."
+        } else {
+            "There is no open door to close."
+        };
+        gamelog.entries.push(message.to_string());
+        false
+    } else {
+        gamelog.entries.push("You close the door.".to_string());
+        true
+    }
 }
 
 pub fn player_input(gs: &mut State, ctx: &mut Rltk) -> RunState {
-    // player movement
-    match ctx.key {
-        // ctx.keyがなにもないとき(何も押されてないとき)にNoneにマッチする
-        // このとき何も起こらない. 再度playerのターンにする
+    // 押されたキーはKeymapで操作に変換する
+    // ctx.keyがなにもないとき(何も押されてないとき)や, 何も割り当てられてないキーのときは
+    // 何も起こらない. 再度playerのターンにする
+    let command = match ctx.key {
         None => return RunState::AwaitingInput,
-        Some(key) => match key {
-            // 上下左右
-            VirtualKeyCode::Left | VirtualKeyCode::Numpad4 | VirtualKeyCode::H => {
-                try_move_player(-1, 0, &mut gs.ecs)
-            }
+        Some(key) => match gs.ecs.fetch::<Keymap>().game_command(key) {
+            None => return RunState::AwaitingInput,
+            Some(command) => command,
+        },
+    };
 
-            VirtualKeyCode::Right | VirtualKeyCode::Numpad6 | VirtualKeyCode::L => {
-                try_move_player(1, 0, &mut gs.ecs)
-            }
+    // StepOnlyのキー(デフォルトはShift)を押しながら歩くと, 自動で拾う設定でも拾わずに乗るだけ
+    let auto_pickup = !gs
+        .ecs
+        .fetch::<Keymap>()
+        .held(Command::StepOnly, rltk::INPUT.lock().key_pressed_set());
 
-            VirtualKeyCode::Up | VirtualKeyCode::Numpad8 | VirtualKeyCode::K => {
-                try_move_player(0, -1, &mut gs.ecs)
-            }
+    match command {
+        // 上下左右
+        Command::MoveLeft => try_move_player(-1, 0, &mut gs.ecs, auto_pickup),
+        Command::MoveRight => try_move_player(1, 0, &mut gs.ecs, auto_pickup),
+        Command::MoveUp => try_move_player(0, -1, &mut gs.ecs, auto_pickup),
+        Command::MoveDown => try_move_player(0, 1, &mut gs.ecs, auto_pickup),
 
-            VirtualKeyCode::Down | VirtualKeyCode::Numpad2 | VirtualKeyCode::J => {
-                try_move_player(0, 1, &mut gs.ecs)
+        // ななめ移動
+        Command::MoveUpRight => try_move_player(1, -1, &mut gs.ecs, auto_pickup),
+        Command::MoveUpLeft => try_move_player(-1, -1, &mut gs.ecs, auto_pickup),
+        Command::MoveDownRight => try_move_player(1, 1, &mut gs.ecs, auto_pickup),
+        Command::MoveDownLeft => try_move_player(-1, 1, &mut gs.ecs, auto_pickup),
+
+        // get an item
+        Command::PickUp => return get_item(&mut gs.ecs),
+
+        // 落とすのも持ち物を選んでからのメニューでやる
+        Command::Inventory | Command::Drop => return RunState::ShowInventory,
+
+        // 隣のドアを閉める. 閉めるドアがなければターンは消費しない
+        Command::CloseDoor => {
+            if !close_adjacent_doors(&mut gs.ecs) {
+                return RunState::AwaitingInput;
             }
+        }
 
-            // ななめ移動
-            VirtualKeyCode::Numpad9 | VirtualKeyCode::U => try_move_player(1, -1, &mut gs.ecs),
-            VirtualKeyCode::Numpad7 | VirtualKeyCode::Y => try_move_player(-1, -1, &mut gs.ecs),
-            VirtualKeyCode::Numpad3 | VirtualKeyCode::M => try_move_player(1, 1, &mut gs.ecs),
-            VirtualKeyCode::Numpad1 | VirtualKeyCode::N => try_move_player(-1, 1, &mut gs.ecs),
+        // 歩くキーと一緒に押すものなので, それだけ押しても何もしない
+        Command::StepOnly => return RunState::AwaitingInput,
 
-            // get an item
-            VirtualKeyCode::G => get_item(&mut gs.ecs),
+        Command::Options => return RunState::ShowOptions,
 
-            VirtualKeyCode::I => return RunState::ShowInventory,
+        Command::CharacterSheet => return RunState::ShowCharacterSheet,
 
-            VirtualKeyCode::D => return RunState::ShowDrpoItem,
+        // 調べるカーソルはplayerのいるところから始める
+        Command::Look => {
+            let cursor = *gs.ecs.fetch::<Point>();
+            return RunState::Looking { cursor };
+        }
 
-            // 無効なキーが押されたときは入力を捨てて再度playerのターンにする
-            _ => return RunState::AwaitingInput,
-        },
+        // wizardのコンソール. カーソルはplayerのいるところから始める
+        Command::WizardConsole => {
+            if !gs.wizard {
+                return RunState::AwaitingInput;
+            }
+            let cursor = *gs.ecs.fetch::<Point>();
+            return RunState::WizardConsole { cursor };
+        }
+
+        // メニュー用の操作はここでは何もしない
+        Command::Cancel
+        | Command::NextTarget
+        | Command::Confirm
+        | Command::CursorLeft
+        | Command::CursorRight
+        | Command::CursorUp
+        | Command::CursorDown
+        | Command::Erase => return RunState::AwaitingInput,
     }
     RunState::MonsterTurn
 }
 
-fn get_item(ecs: &mut World) {
-    let player_pos = ecs.fetch::<Point>();
-    let player_entity = ecs.fetch::<Entity>();
-    let entites = ecs.entities();
-    let items = ecs.read_storage::<Item>();
-    let positions = ecs.read_storage::<Position>();
-    let mut gamelog = ecs.fetch_mut::<GameLog>();
+/// 足元のアイテムを拾う. いくつもあったらどれを拾うかメニューで選ぶ
+fn get_item(ecs: &mut World) -> RunState {
+    let player_pos = *ecs.fetch::<Point>();
+    let items = items_at(ecs, player_pos);
+    match items.len() {
+        0 => {
+            ecs.fetch_mut::<GameLog>()
+                .entries
+                .push("There is nothing here to pick up.".to_string());
+            RunState::AwaitingInput
+        }
+        1 => {
+            pickup_items(ecs, &items);
+            RunState::MonsterTurn
+        }
+        _ => RunState::ShowPickupMenu,
+    }
+}
+
+/// playerがitemsを拾う. 実際に拾うのはItemCollectionSystem
+pub fn pickup_items(ecs: &mut World, items: &[Entity]) {
+    let player_entity = *ecs.fetch::<Entity>();
+    let mut pickup = ecs.write_storage::<WantsToPickupItem>();
+    for item in items.iter() {
+        pickup
+            .insert(
+                *item,
+                WantsToPickupItem {
+                    collected_by: player_entity,
+                    item: *item,
+                },
+            )
+            .expect("Unable to insert want to pickup");
+    }
+}
 
-    let mut target_item: Option<Entity> = None;
-    for (item_entity, _item, position) in (&entites, &items, &positions).join() {
-        if position.x == player_pos.x && position.y == player_pos.y {
-            target_item = Some(item_entity);
+/// 足元に落ちてるものを教える. 自動で拾ったものは拾ったときのログが出るので数えない
+fn report_items_here(ecs: &World, picked: &[Entity]) {
+    let player_pos = *ecs.fetch::<Point>();
+    let mut items = items_at(ecs, player_pos);
+    items.retain(|item| !picked.contains(item));
+    let message = match items.len() {
+        0 => return,
+        1 => format!("You see {} here.", describe::item_name(ecs, items[0])),
+        n => format!("You see {} items here.", n),
+    };
+    ecs.fetch_mut::<GameLog>().entries.push(message);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        describe::FlavourText, map_indexing_system::MapIndexingSystem, settings::Settings, spawner,
+        TileType,
+    };
+    use rltk::VirtualKeyCode;
+    use std::collections::HashSet;
+
+    // (2, 2)にplayerがいて, 右隣にRed Potionが落ちてるworld
+    fn world_with_potion() -> (World, Entity) {
+        let settings = Settings {
+            auto_pickup: true,
+            ..Settings::default()
+        };
+        let mut ecs = State::new(
+            None,
+            false,
+            settings,
+            Keymap::default_bindings(),
+            FlavourText::load().unwrap(),
+        )
+        .ecs;
+        let mut map = Map::new(1, 10, 5);
+        for x in 1..9 {
+            let idx = map.xy_idx(x, 2);
+            map.tiles[idx] = TileType::Floor;
         }
+        ecs.insert(map);
+        let player = *ecs.fetch::<Entity>();
+        ecs.write_storage::<Position>()
+            .insert(player, Position { x: 2, y: 2 })
+            .unwrap();
+        *ecs.write_resource::<Point>() = Point::new(2, 2);
+        let potion = spawner::spawn_named(&mut ecs, "Red Potion", 3, 2).unwrap();
+        MapIndexingSystem {}.run_now(&ecs);
+        (ecs, potion)
     }
 
-    match target_item {
-        None => gamelog
-            .entries
-            .push("There is nothing here to pick up.".to_string()),
-        Some(item) => {
-            let mut pickup = ecs.write_storage::<WantsToPickupItem>();
-            pickup
-                .insert(
-                    *player_entity,
-                    WantsToPickupItem {
-                        collected_by: *player_entity,
-                        item,
-                    },
-                )
-                .expect("Unable to insert want to pickup");
+    #[test]
+    fn stepping_onto_an_item_picks_it_up_unless_step_only_is_held() {
+        let keymap = Keymap::default_bindings();
+        let mut pressed = HashSet::new();
+        for step_only in [false, true] {
+            let (mut ecs, potion) = world_with_potion();
+            if step_only {
+                pressed.insert(VirtualKeyCode::LShift);
+            }
+            let auto_pickup = !keymap.held(Command::StepOnly, &pressed);
+            try_move_player(1, 0, &mut ecs, auto_pickup);
+
+            assert_eq!(*ecs.fetch::<Point>(), Point::new(3, 2));
+            let wants = ecs.read_storage::<WantsToPickupItem>();
+            assert_eq!(wants.get(potion).is_some(), !step_only);
+            if step_only {
+                let log = ecs.fetch::<GameLog>();
+                assert_eq!(log.entries.last().unwrap(), "You see Red Potion here.");
+            }
         }
     }
 }
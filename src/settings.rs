@@ -0,0 +1,186 @@
+use super::{
+    camera::{SCREEN_HEIGHT, SCREEN_WIDTH},
+    config,
+    inventory_system::{ItemCategory, MAX_BACKPACK_SLOTS},
+    tileset,
+};
+use rltk::{embedded_resource, link_resource, BResult, Rltk, RltkBuilder};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+
+const SETTINGS_FILE: &str = "settings.toml";
+
+// GameLogに残しておくメッセージの数. オプション画面ではこの中から順番に選ぶ
+const LOG_LENGTHS: [usize; 4] = [50, 100, 200, 500];
+
+// resources/vga8x16.jpgはバイナリに埋め込んでおく
+// 実行するときのカレントディレクトリがどこでもフォントが読めるように
+embedded_resource!(VGA_FONT, "../resources/vga8x16.jpg");
+
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Font {
+    Terminal8x8,
+    Vga8x16,
+}
+
+impl Font {
+    pub fn label(self) -> &'static str {
+        match self {
+            Font::Terminal8x8 => "8x8",
+            Font::Vga8x16 => "VGA 8x16",
+        }
+    }
+
+    fn next(self) -> Font {
+        match self {
+            Font::Terminal8x8 => Font::Vga8x16,
+            Font::Vga8x16 => Font::Terminal8x8,
+        }
+    }
+}
+
+/// オプション画面で変えられる設定. settings.tomlに保存される
+/// ファイルに書かれてない項目はデフォルトのまま
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub scanlines: bool,
+    pub font: Font,
+    pub log_length: usize,
+    pub auto_pickup: bool,
+    // 自動で拾うアイテムの種類. auto_pickupがOffなら何も拾わない
+    pub auto_pickup_categories: Vec<ItemCategory>,
+    // 持ち物の数の上限. まとめたアイテムは1つと数える. a-zで選べる26個までしか増やせない
+    pub backpack_capacity: usize,
+    // trueならタイル画像で描く. falseならASCII
+    pub tiles: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            scanlines: true,
+            font: Font::Terminal8x8,
+            log_length: 100,
+            auto_pickup: false,
+            auto_pickup_categories: vec![ItemCategory::Potion, ItemCategory::Scroll],
+            backpack_capacity: 20,
+            tiles: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Io(String),
+    Parse(String),
+    NoConfigDir,
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingsError::Io(e) => write!(f, "Unable to access {}: {}", SETTINGS_FILE, e),
+            SettingsError::Parse(e) => write!(f, "Unable to parse {}: {}", SETTINGS_FILE, e),
+            SettingsError::NoConfigDir => write!(f, "No config directory for {}", SETTINGS_FILE),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+impl Settings {
+    /// ユーザーの設定ファイルを読む. なければデフォルト
+    pub fn load() -> Result<Settings, SettingsError> {
+        let path = match config::config_file(SETTINGS_FILE) {
+            Some(path) if path.exists() => path,
+            _ => return Ok(Settings::default()),
+        };
+        let text = fs::read_to_string(&path).map_err(|e| SettingsError::Io(e.to_string()))?;
+        toml::from_str(&text).map_err(|e| SettingsError::Parse(e.to_string()))
+    }
+
+    pub fn save(&self) -> Result<(), SettingsError> {
+        let path = config::config_file(SETTINGS_FILE).ok_or(SettingsError::NoConfigDir)?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| SettingsError::Io(e.to_string()))?;
+        }
+        let text = toml::to_string(self).map_err(|e| SettingsError::Parse(e.to_string()))?;
+        fs::write(&path, text).map_err(|e| SettingsError::Io(e.to_string()))
+    }
+
+    /// 実際に使える持ち物の枠の数. ファイルに変な値が書かれててもa-zの範囲に収める
+    pub fn backpack_slots(&self) -> usize {
+        self.backpack_capacity.clamp(1, MAX_BACKPACK_SLOTS)
+    }
+
+    pub fn toggle_scanlines(&mut self) {
+        self.scanlines = !self.scanlines;
+    }
+
+    pub fn next_font(&mut self) {
+        self.font = self.font.next();
+    }
+
+    pub fn toggle_tiles(&mut self) {
+        self.tiles = !self.tiles;
+    }
+
+    pub fn toggle_auto_pickup(&mut self) {
+        self.auto_pickup = !self.auto_pickup;
+    }
+
+    pub fn toggle_auto_pickup_category(&mut self, category: ItemCategory) {
+        if self.auto_pickup_categories.contains(&category) {
+            self.auto_pickup_categories.retain(|c| *c != category);
+        } else {
+            self.auto_pickup_categories.push(category);
+        }
+    }
+
+    /// 今の長さの次の候補にする. 一覧にない値がファイルに書かれてたら最初の候補に戻す
+    pub fn next_log_length(&mut self) {
+        self.log_length = match LOG_LENGTHS.iter().position(|l| *l == self.log_length) {
+            Some(i) => LOG_LENGTHS[(i + 1) % LOG_LENGTHS.len()],
+            None => LOG_LENGTHS[0],
+        };
+    }
+
+    /// 画面の設定を反映させる. フォントは起動したときしか変えられないのでここでは触らない
+    pub fn apply(&self, ctx: &mut Rltk) {
+        ctx.post_scanlines = self.scanlines;
+        ctx.post_screenburn = self.scanlines;
+    }
+
+    /// 設定されたフォントでコンソールをつくる
+    /// どっちのフォントでも80x50マスなので, 画面のレイアウトは変わらない
+    /// レイヤーはcamera::MAP_LAYER, TILE_LAYER, UI_LAYERの順に重なる
+    pub fn build_context(&self, title: &str) -> BResult<Rltk> {
+        let (builder, font) = match self.font {
+            Font::Terminal8x8 => (
+                RltkBuilder::simple(SCREEN_WIDTH, SCREEN_HEIGHT)?,
+                "terminal8x8.png",
+            ),
+            Font::Vga8x16 => {
+                link_resource!(VGA_FONT, "resources/vga8x16.jpg");
+                let builder = RltkBuilder::new()
+                    .with_dimensions(SCREEN_WIDTH, SCREEN_HEIGHT)
+                    .with_tile_dimensions(8, 16)
+                    .with_font("vga8x16.jpg", 8, 16)
+                    .with_simple_console(SCREEN_WIDTH, SCREEN_HEIGHT, "vga8x16.jpg");
+                (builder, "vga8x16.jpg")
+            }
+        };
+
+        // タイル画像のレイヤーは背景なしにして, 下のASCIIのレイヤーに重ねる
+        tileset::link_sheet();
+        builder
+            .with_font(tileset::TILE_SHEET, tileset::TILE_SIZE, tileset::TILE_SIZE)
+            .with_sparse_console_no_bg(SCREEN_WIDTH, SCREEN_HEIGHT, tileset::TILE_SHEET)
+            .with_sparse_console(SCREEN_WIDTH, SCREEN_HEIGHT, font)
+            .with_title(title)
+            .build()
+    }
+}
@@ -1,12 +1,15 @@
 use super::{
-    map::MAPWIDTH, AreaOfEffect, BlocksTile, CombatStats, Confusion, Consumable, InflictsDamage,
-    Item, Monster, Name, Player, Position, ProvidesHealing, Ranged, Rect, Renderable, Viewshed,
+    AreaOfEffect, BlocksTile, BlocksVisibility, Bolt, CombatStats, Confusion, Consumable, Door,
+    InflictsDamage, Item, LightSource, Map, Monster, Name, Player, Position, ProvidesHealing,
+    Ranged, Renderable, Stackable, Swimmer, Viewshed,
 };
 use rltk::{RandomNumberGenerator, RGB};
 use specs::prelude::*;
 
 const MAX_MONSTERS: i32 = 4;
 const MAX_ITEMS: i32 = 2;
+// 1つのregionに松明が置かれる確率
+const TORCH_PERCENT: i32 = 30;
 
 /// playerを出現させてそのentityを返す
 pub fn player(ecs: &mut World, player_x: i32, player_y: i32) -> Entity {
@@ -30,6 +33,10 @@ pub fn player(ecs: &mut World, player_x: i32, player_y: i32) -> Entity {
         .with(Name {
             name: "Pon".to_string(),
         })
+        .with(LightSource {
+            radius: 2,
+            colour: RGB::from_f32(0.6, 0.6, 0.5),
+        })
         .with(CombatStats {
             max_hp: 30,
             hp: 30,
@@ -39,40 +46,103 @@ pub fn player(ecs: &mut World, player_x: i32, player_y: i32) -> Entity {
         .build()
 }
 
+pub fn random_monster_name(rng: &mut RandomNumberGenerator) -> &'static str {
+    match rng.roll_dice(1, 5) {
+        1 | 2 => "Orc",
+        3 | 4 => "Goblin",
+        _ => "Will-o'-Wisp",
+    }
+}
+
+pub fn random_item_name(rng: &mut RandomNumberGenerator) -> &'static str {
+    match rng.roll_dice(1, 4) {
+        1 => "Red Potion",
+        2 => "Fireball Scroll",
+        3 => "Confusion Scroll",
+        _ => "Magic Missile Scroll",
+    }
+}
+
 pub fn random_monster(ecs: &mut World, x: i32, y: i32) {
-    let roll: i32;
+    let name;
     {
         let mut rng = ecs.write_resource::<RandomNumberGenerator>();
-        roll = rng.roll_dice(1, 2);
-    }
-    match roll {
-        1 => orc(ecs, x, y),
-        _ => goblin(ecs, x, y),
+        name = random_monster_name(&mut rng);
     }
+    spawn_named(ecs, name, x, y);
 }
 
 pub fn random_item(ecs: &mut World, x: i32, y: i32) {
-    let roll: i32;
+    let name;
     {
         let mut rng = ecs.write_resource::<RandomNumberGenerator>();
-        roll = rng.roll_dice(1, 4);
-    }
-    match roll {
-        1 => health_potion(ecs, x, y),
-        2 => fireball_scroll(ecs, x, y),
-        3 => confusion_scroll(ecs, x, y),
-        _ => magic_missile_scroll(ecs, x, y),
+        name = random_item_name(&mut rng);
     }
+    spawn_named(ecs, name, x, y);
+}
+
+type SpawnFn = fn(&mut World, i32, i32) -> Entity;
+
+// 名前で出せるentity. 名前はNameと同じにする
+const SPAWN_TABLE: &[(&str, SpawnFn)] = &[
+    ("Orc", orc),
+    ("Goblin", goblin),
+    ("Will-o'-Wisp", wisp),
+    ("Red Potion", health_potion),
+    ("Magic Missile Scroll", magic_missile_scroll),
+    ("Fireball Scroll", fireball_scroll),
+    ("Confusion Scroll", confusion_scroll),
+    ("Door", door),
+    ("Torch", torch),
+];
+
+/// 名前で出せるentityの名前ぜんぶ
+pub fn spawnable_names() -> impl Iterator<Item = &'static str> {
+    SPAWN_TABLE.iter().map(|(name, _)| *name)
 }
 
-fn orc(ecs: &mut World, x: i32, y: i32) {
-    monster(ecs, x, y, rltk::to_cp437('o'), "Orc");
+/// 名前を指定してentityを出現させる. 知らない名前ならNone
+pub fn spawn_named(ecs: &mut World, name: &str, x: i32, y: i32) -> Option<Entity> {
+    SPAWN_TABLE
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, spawn)| spawn(ecs, x, y))
 }
-fn goblin(ecs: &mut World, x: i32, y: i32) {
-    monster(ecs, x, y, rltk::to_cp437('g'), "Goblin");
+
+fn orc(ecs: &mut World, x: i32, y: i32) -> Entity {
+    monster(ecs, x, y, rltk::to_cp437('o'), "Orc")
+}
+// 泳げるので, 深い水を渡って追いかけてくる
+fn goblin(ecs: &mut World, x: i32, y: i32) -> Entity {
+    let entity = monster(ecs, x, y, rltk::to_cp437('g'), "Goblin");
+    ecs.write_storage::<Swimmer>()
+        .insert(entity, Swimmer {})
+        .expect("Unable to insert Swimmer");
+    entity
+}
+
+// 青白く光って, 暗いフロアでも居場所がわかる
+fn wisp(ecs: &mut World, x: i32, y: i32) -> Entity {
+    let entity = monster(ecs, x, y, rltk::to_cp437('w'), "Will-o'-Wisp");
+    ecs.write_storage::<LightSource>()
+        .insert(
+            entity,
+            LightSource {
+                radius: 4,
+                colour: RGB::named(rltk::LIGHT_BLUE),
+            },
+        )
+        .expect("Unable to insert light");
+    entity
 }
 
-fn monster<S: ToString>(ecs: &mut World, x: i32, y: i32, glyph: rltk::FontCharType, name: S) {
+fn monster<S: ToString>(
+    ecs: &mut World,
+    x: i32,
+    y: i32,
+    glyph: rltk::FontCharType,
+    name: S,
+) -> Entity {
     ecs.create_entity()
         .with(Position { x, y })
         .with(Renderable {
@@ -97,10 +167,10 @@ fn monster<S: ToString>(ecs: &mut World, x: i32, y: i32, glyph: rltk::FontCharTy
             defense: 1,
             power: 4,
         })
-        .build();
+        .build()
 }
 
-fn health_potion(ecs: &mut World, x: i32, y: i32) {
+fn health_potion(ecs: &mut World, x: i32, y: i32) -> Entity {
     ecs.create_entity()
         .with(Position { x, y })
         .with(Renderable {
@@ -113,12 +183,13 @@ fn health_potion(ecs: &mut World, x: i32, y: i32) {
             name: "Red Potion".to_string(),
         })
         .with(Item {})
+        .with(Stackable { count: 1 })
         .with(Consumable {})
         .with(ProvidesHealing { heal_amount: 8 })
-        .build();
+        .build()
 }
 
-fn magic_missile_scroll(ecs: &mut World, x: i32, y: i32) {
+fn magic_missile_scroll(ecs: &mut World, x: i32, y: i32) -> Entity {
     ecs.create_entity()
         .with(Position { x, y })
         .with(Renderable {
@@ -131,13 +202,15 @@ fn magic_missile_scroll(ecs: &mut World, x: i32, y: i32) {
             name: "Magic Missile Scroll".to_string(),
         })
         .with(Item {})
+        .with(Stackable { count: 1 })
         .with(Consumable {})
         .with(Ranged { range: 6 })
+        .with(Bolt {})
         .with(InflictsDamage { damage: 8 })
-        .build();
+        .build()
 }
 
-fn fireball_scroll(ecs: &mut World, x: i32, y: i32) {
+fn fireball_scroll(ecs: &mut World, x: i32, y: i32) -> Entity {
     ecs.create_entity()
         .with(Position { x, y })
         .with(Renderable {
@@ -150,14 +223,15 @@ fn fireball_scroll(ecs: &mut World, x: i32, y: i32) {
             name: "Fireball Scroll".to_string(),
         })
         .with(Item {})
+        .with(Stackable { count: 1 })
         .with(Consumable {})
         .with(Ranged { range: 6 })
         .with(InflictsDamage { damage: 20 })
         .with(AreaOfEffect { radius: 3 })
-        .build();
+        .build()
 }
 
-fn confusion_scroll(ecs: &mut World, x: i32, y: i32) {
+fn confusion_scroll(ecs: &mut World, x: i32, y: i32) -> Entity {
     ecs.create_entity()
         .with(Position { x, y })
         .with(Renderable {
@@ -170,67 +244,106 @@ fn confusion_scroll(ecs: &mut World, x: i32, y: i32) {
             name: "Confusion Scroll".to_string(),
         })
         .with(Item {})
+        .with(Stackable { count: 1 })
         .with(Consumable {})
         .with(Ranged { range: 6 })
         .with(Confusion { turns: 4 })
-        .build();
+        .build()
+}
+
+/// 閉じた状態のドア. 通るのも見通すのも邪魔する
+fn door(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: rltk::to_cp437('+'),
+            fg: RGB::named(rltk::CHOCOLATE),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Door".to_string(),
+        })
+        .with(Door { open: false })
+        .with(BlocksTile {})
+        .with(BlocksVisibility {})
+        .build()
+}
+
+/// 床置きの松明. まわりを暖かい色で照らす
+fn torch(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x, y })
+        .with(Renderable {
+            glyph: rltk::to_cp437('☼'),
+            fg: RGB::named(rltk::YELLOW),
+            bg: RGB::named(rltk::BLACK),
+            render_order: 2,
+        })
+        .with(Name {
+            name: "Torch".to_string(),
+        })
+        .with(LightSource {
+            radius: 6,
+            colour: RGB::named(rltk::ORANGE),
+        })
+        .build()
 }
 
 /// itemの設置
 /// rngとmapを取得して,サイコロふってモンスターを何匹出すか決める
-/// そして, その数だけモンスターを出現させようとする
-pub fn spawn_room(ecs: &mut World, room: &Rect) {
+/// そして, その数だけregionの中のタイルからランダムに場所を選んで出現させる
+pub fn spawn_region(ecs: &mut World, area: &[usize]) {
     let mut monster_spawn_points: Vec<usize> = Vec::new();
     let mut item_spawn_points: Vec<usize> = Vec::new();
+    let mut torch_spawn_point: Option<usize> = None;
+    let map_width = ecs.fetch::<Map>().width as usize;
 
     // borrow checkerを満たすためのスコープ
     {
         let mut rng = ecs.write_resource::<RandomNumberGenerator>();
+        let mut areas: Vec<usize> = Vec::from(area);
         let num_monsters = rng.roll_dice(1, MAX_MONSTERS + 2) - 3;
         let num_items = rng.roll_dice(1, MAX_ITEMS + 2) - 3;
 
+        // 選んだタイルはareasから抜くので, 同じセルに2つ湧くことはない
         for _i in 0..num_monsters {
-            let mut added = false;
-            while !added {
-                let x = (room.x1 + rng.roll_dice(1, i32::abs(room.x2 - room.x1))) as usize;
-                let y = (room.y1 + rng.roll_dice(1, i32::abs(room.y2 - room.y1))) as usize;
-                let idx = (y * MAPWIDTH) + x;
-
-                // 同じセルにmonsterが湧かないようにする
-                if !monster_spawn_points.contains(&idx) {
-                    monster_spawn_points.push(idx);
-                    added = true;
-                }
+            if areas.is_empty() {
+                break;
             }
+            let array_idx = (rng.roll_dice(1, areas.len() as i32) - 1) as usize;
+            monster_spawn_points.push(areas.remove(array_idx));
         }
 
         for _i in 0..num_items {
-            let mut added = false;
-            while !added {
-                let x = (room.x1 + rng.roll_dice(1, i32::abs(room.x2 - room.x1))) as usize;
-                let y = (room.y1 + rng.roll_dice(1, i32::abs(room.y2 - room.y1))) as usize;
-                let idx = (y * MAPWIDTH) + x;
-
-                // 同じセルにitemが湧かないようにする
-                if !item_spawn_points.contains(&idx) {
-                    item_spawn_points.push(idx);
-                    added = true;
-                }
+            if areas.is_empty() {
+                break;
             }
+            let array_idx = (rng.roll_dice(1, areas.len() as i32) - 1) as usize;
+            item_spawn_points.push(areas.remove(array_idx));
+        }
+
+        if !areas.is_empty() && rng.roll_dice(1, 100) <= TORCH_PERCENT {
+            let array_idx = (rng.roll_dice(1, areas.len() as i32) - 1) as usize;
+            torch_spawn_point = Some(areas.remove(array_idx));
         }
     } // ここでrngをDropさせる
 
     // 実際にmonsterを出現させる
     for idx in monster_spawn_points.iter() {
-        let x = *idx % MAPWIDTH;
-        let y = *idx / MAPWIDTH;
+        let x = *idx % map_width;
+        let y = *idx / map_width;
         random_monster(ecs, x as i32, y as i32);
     }
 
     // 実際にpotionを出現させる
     for idx in item_spawn_points.iter() {
-        let x = *idx % MAPWIDTH;
-        let y = *idx / MAPWIDTH;
+        let x = *idx % map_width;
+        let y = *idx / map_width;
         random_item(ecs, x as i32, y as i32);
     }
+
+    if let Some(idx) = torch_spawn_point {
+        torch(ecs, (idx % map_width) as i32, (idx / map_width) as i32);
+    }
 }
@@ -0,0 +1,15 @@
+/// このゲームでplayerがしてきたことの記録. キャラクター画面で見られる
+#[derive(Default, Clone, Debug)]
+pub struct Statistics {
+    // playerが行動した回数. monsterのターンが来るたびに数える
+    pub turns: i32,
+    pub kills: i32,
+    pub damage_dealt: i32,
+    pub damage_taken: i32,
+    // 拾ったアイテムの数. 自分で置いたものを拾いなおしても増えない
+    pub items_found: i32,
+    // playerが倒したmonsterの名前. 倒した順
+    pub kill_list: Vec<String>,
+    // playerが倒れたときに何にやられたか. 生きてるあいだはNone
+    pub cause_of_death: Option<String>,
+}
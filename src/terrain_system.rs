@@ -0,0 +1,112 @@
+use super::{gamelog::GameLog, EnteredTile, Item, Map, Name, Position, SufferDamage, TileType};
+use specs::prelude::*;
+
+const LAVA_DAMAGE: i32 = 6;
+
+/// タイルに入ったentityに地形の効果を与える. 深い水の上のアイテムは, どうやって来ても沈む
+pub struct TerrainSystem {}
+
+impl<'a> System<'a> for TerrainSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        ReadExpect<'a, Map>,
+        ReadExpect<'a, Entity>,
+        WriteExpect<'a, GameLog>,
+        Entities<'a>,
+        WriteStorage<'a, EnteredTile>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Name>,
+        WriteStorage<'a, SufferDamage>,
+        ReadStorage<'a, Item>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (
+            map,
+            player_entity,
+            mut gamelog,
+            entities,
+            mut entered,
+            positions,
+            names,
+            mut suffer_damage,
+            items,
+        ) = data;
+
+        for (entity, _entered, pos) in (&entities, &entered, &positions).join() {
+            let idx = map.xy_idx(pos.x, pos.y);
+            if map.tiles[idx] == TileType::Lava {
+                SufferDamage::new_damage(&mut suffer_damage, entity, LAVA_DAMAGE, None);
+                if entity == *player_entity {
+                    gamelog.entries.push(format!(
+                        "You are burned by the lava, taking {} hp.",
+                        LAVA_DAMAGE
+                    ));
+                } else if map.visible_tiles[idx] {
+                    if let Some(name) = names.get(entity) {
+                        gamelog
+                            .entries
+                            .push(format!("{} is burned by the lava.", name.name));
+                    }
+                }
+            }
+        }
+
+        entered.clear();
+
+        for (entity, _item, pos) in (&entities, &items, &positions).join() {
+            let idx = map.xy_idx(pos.x, pos.y);
+            if map.tiles[idx] != TileType::DeepWater {
+                continue;
+            }
+            if map.visible_tiles[idx] {
+                if let Some(name) = names.get(entity) {
+                    gamelog
+                        .entries
+                        .push(format!("The {} sinks out of sight.", name.name));
+                }
+            }
+            entities
+                .delete(entity)
+                .expect("Unable to delete sunken item");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{describe::FlavourText, keymap::Keymap, settings::Settings, spawner, State};
+
+    #[test]
+    fn items_left_on_deep_water_sink() {
+        let mut ecs = State::new(
+            None,
+            false,
+            Settings::default(),
+            Keymap::default_bindings(),
+            FlavourText::load().unwrap(),
+        )
+        .ecs;
+        let mut map = Map::new(1, 5, 3);
+        for x in 1..4 {
+            let idx = map.xy_idx(x, 1);
+            map.tiles[idx] = TileType::Floor;
+        }
+        let water = map.xy_idx(3, 1);
+        map.tiles[water] = TileType::DeepWater;
+        ecs.insert(map);
+
+        // 投げたんじゃなくて, ただそこに置かれたアイテム
+        let sunk = spawner::spawn_named(&mut ecs, "Red Potion", 3, 1).unwrap();
+        let kept = spawner::spawn_named(&mut ecs, "Red Potion", 2, 1).unwrap();
+        TerrainSystem {}.run_now(&ecs);
+        ecs.maintain();
+
+        assert!(!ecs.is_alive(sunk));
+        assert_eq!(
+            ecs.read_storage::<Position>().get(kept),
+            Some(&Position { x: 2, y: 1 })
+        );
+    }
+}
@@ -0,0 +1,182 @@
+use super::{camera, Map, Name, Position, Renderable, TileType};
+use rltk::{embedded_resource, link_resource, FontCharType, Point, Rltk, RGB};
+use specs::prelude::*;
+
+// タイル画像. 16x16ピクセルのタイルが16x16個並んでいて, 左上から右へ0, 1, 2...と番号がつく
+pub const TILE_SHEET: &str = "example_tiles.jpg";
+pub const TILE_SIZE: u32 = 16;
+
+embedded_resource!(TILE_SHEET_IMAGE, "../resources/example_tiles.jpg");
+
+/// 実行するときのカレントディレクトリがどこでもタイル画像が読めるように, 埋め込んだ画像を登録する
+pub fn link_sheet() {
+    link_resource!(TILE_SHEET_IMAGE, "resources/example_tiles.jpg");
+}
+
+const TILE_WALL: FontCharType = 0;
+const TILE_FLOOR: FontCharType = 1;
+const TILE_PLAYER: FontCharType = 2;
+const TILE_DEEP_WATER: FontCharType = 3;
+const TILE_SHALLOW_WATER: FontCharType = 4;
+const TILE_LAVA: FontCharType = 5;
+const TILE_RUBBLE: FontCharType = 6;
+const TILE_BRIDGE: FontCharType = 7;
+const TILE_ORC: FontCharType = 8;
+const TILE_GOBLIN: FontCharType = 9;
+const TILE_WISP: FontCharType = 10;
+const TILE_POTION: FontCharType = 11;
+const TILE_MAGIC_MISSILE_SCROLL: FontCharType = 12;
+const TILE_FIREBALL_SCROLL: FontCharType = 13;
+const TILE_CONFUSION_SCROLL: FontCharType = 14;
+const TILE_DOOR: FontCharType = 15;
+const TILE_OPEN_DOOR: FontCharType = 16;
+const TILE_TORCH: FontCharType = 17;
+
+// entityのNameとタイル番号の対応. ここにないentityはASCIIのまま描く
+// ドアは開け閉めでASCIIの文字が変わるので, 文字でも絵を分ける. Noneならどの文字でも同じ絵
+const ENTITY_TILES: &[(&str, Option<char>, FontCharType)] = &[
+    ("Pon", None, TILE_PLAYER),
+    ("Orc", None, TILE_ORC),
+    ("Goblin", None, TILE_GOBLIN),
+    ("Will-o'-Wisp", None, TILE_WISP),
+    ("Red Potion", None, TILE_POTION),
+    ("Magic Missile Scroll", None, TILE_MAGIC_MISSILE_SCROLL),
+    ("Fireball Scroll", None, TILE_FIREBALL_SCROLL),
+    ("Confusion Scroll", None, TILE_CONFUSION_SCROLL),
+    ("Door", Some('+'), TILE_DOOR),
+    ("Door", Some('\''), TILE_OPEN_DOOR),
+    ("Torch", None, TILE_TORCH),
+];
+
+/// マップのタイルの絵. 絵がない地形はNone
+pub fn tile_for_tiletype(tile: TileType) -> Option<FontCharType> {
+    match tile {
+        TileType::Wall => Some(TILE_WALL),
+        TileType::Floor => Some(TILE_FLOOR),
+        TileType::DeepWater => Some(TILE_DEEP_WATER),
+        TileType::ShallowWater => Some(TILE_SHALLOW_WATER),
+        TileType::Lava => Some(TILE_LAVA),
+        TileType::Rubble => Some(TILE_RUBBLE),
+        TileType::Bridge => Some(TILE_BRIDGE),
+    }
+}
+
+/// entityの絵. 名前と, いまASCIIで描いてる文字で探す
+pub fn tile_for_entity(name: &str, glyph: FontCharType) -> Option<FontCharType> {
+    ENTITY_TILES
+        .iter()
+        .find(|(n, c, _)| *n == name && c.is_none_or(|c| rltk::to_cp437(c) == glyph))
+        .map(|(_, _, tile)| *tile)
+}
+
+/// render_cameraがASCIIで描いたあとに, 絵のあるマスをタイルのレイヤーに描きなおす
+/// 地形と一番上のentityの両方に絵があるマスだけ絵にして, それ以外はASCIIのまま残す
+pub fn render_tiles(ecs: &World, ctx: &mut Rltk) {
+    let origin = camera::view_origin(ecs);
+    let map = ecs.fetch::<Map>();
+    let positions = ecs.read_storage::<Position>();
+    let renderables = ecs.read_storage::<Renderable>();
+    let names = ecs.read_storage::<Name>();
+
+    // マスごとに一番上に見えてるentityの絵. ASCIIと同じ順番で上書きしていく
+    let mut data = (&positions, &renderables, names.maybe())
+        .join()
+        .collect::<Vec<_>>();
+    data.sort_by_key(|&a| std::cmp::Reverse(a.1.render_order));
+    let mut top_entity: Vec<Option<Option<FontCharType>>> = vec![None; map.tiles.len()];
+    for (pos, render, name) in data.iter() {
+        let idx = map.xy_idx(pos.x, pos.y);
+        if map.visible_tiles[idx] {
+            top_entity[idx] = Some(name.and_then(|n| tile_for_entity(&n.name, render.glyph)));
+        }
+    }
+
+    let mut cells: Vec<(Point, RGB, Vec<FontCharType>)> = Vec::new();
+    for sy in 0..camera::VIEW_HEIGHT {
+        for sx in 0..camera::VIEW_WIDTH {
+            let x = sx + origin.x;
+            let y = sy + origin.y;
+            if x < 0 || x >= map.width || y < 0 || y >= map.height {
+                continue;
+            }
+            let idx = map.xy_idx(x, y);
+            if !map.revealed_tiles[idx] {
+                continue;
+            }
+
+            let tiles = match (tile_for_tiletype(map.tiles[idx]), top_entity[idx]) {
+                (Some(tile), None) => vec![tile],
+                (Some(tile), Some(Some(entity))) => vec![tile, entity],
+                _ => continue,
+            };
+            // 見えてるマスは明かりの色で染めて, 覚えてるだけのマスは暗くする
+            let tint = if map.visible_tiles[idx] {
+                map.light[idx]
+            } else {
+                RGB::from_f32(0.4, 0.4, 0.4)
+            };
+            cells.push((Point::new(sx, sy), tint, tiles));
+        }
+    }
+
+    // 絵にするマスのASCIIは消しておく. 絵の黒いところから透けて見えないように
+    ctx.set_active_console(camera::MAP_LAYER);
+    for (screen, _, _) in cells.iter() {
+        ctx.set(
+            screen.x,
+            screen.y,
+            RGB::named(rltk::BLACK),
+            RGB::named(rltk::BLACK),
+            rltk::to_cp437(' '),
+        );
+    }
+
+    ctx.set_active_console(camera::TILE_LAYER);
+    for (screen, tint, tiles) in cells.iter() {
+        for tile in tiles.iter() {
+            ctx.set(screen.x, screen.y, *tint, RGB::named(rltk::BLACK), *tile);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{describe::FlavourText, keymap::Keymap, settings::Settings, spawner, State};
+
+    #[test]
+    fn every_terrain_has_a_tile() {
+        for tile in TileType::ALL.iter() {
+            assert!(tile_for_tiletype(*tile).is_some(), "{:?}", tile);
+        }
+    }
+
+    #[test]
+    fn every_spawned_entity_has_a_tile() {
+        let mut ecs = State::new(
+            None,
+            false,
+            Settings::default(),
+            Keymap::default_bindings(),
+            FlavourText::load().unwrap(),
+        )
+        .ecs;
+        let mut entities = vec![spawner::player(&mut ecs, 0, 0)];
+        for name in spawner::spawnable_names() {
+            entities.push(spawner::spawn_named(&mut ecs, name, 0, 0).unwrap());
+        }
+
+        let names = ecs.read_storage::<Name>();
+        let renderables = ecs.read_storage::<Renderable>();
+        for entity in entities {
+            let name = &names.get(entity).unwrap().name;
+            let glyph = renderables.get(entity).unwrap().glyph;
+            assert!(tile_for_entity(name, glyph).is_some(), "{}", name);
+        }
+        // 開いたドアは文字が変わるけど, 絵はある
+        assert_eq!(
+            tile_for_entity("Door", rltk::to_cp437('\'')),
+            Some(TILE_OPEN_DOOR)
+        );
+    }
+}
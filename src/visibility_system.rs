@@ -1,5 +1,5 @@
-use super::{Map, Player, Position, Viewshed};
-use rltk::{field_of_view, Point};
+use super::{Map, Player, Position, Viewshed, VisibilityMode};
+use rltk::{field_of_view, DistanceAlg, Point};
 use specs::prelude::*;
 
 pub struct VisibilitySystem {}
@@ -20,13 +20,24 @@ impl<'a> System<'a> for VisibilitySystem {
             if viewshed.dirty {
                 viewshed.dirty = false;
                 viewshed.visible_tiles.clear();
-                viewshed.visible_tiles =
-                    field_of_view(Point::new(pos.x, pos.y), viewshed.range, &*map);
+                let origin = Point::new(pos.x, pos.y);
+                viewshed.visible_tiles = match map.visibility_mode {
+                    VisibilityMode::Radius => field_of_view(origin, viewshed.range, &*map),
+                    VisibilityMode::Rooms => room_view(&map, origin),
+                };
                 viewshed
                     .visible_tiles
                     // 条件を満たす要素だけ残す.JSのfilterのようなものだけど破壊的.元の配列を変更する.
                     .retain(|p| p.x >= 0 && p.x < map.width && p.y >= 0 && p.y < map.height);
 
+                // 暗いフロアでは, 明かりのあるところと隣のタイルしか見えない
+                if map.dark {
+                    viewshed.visible_tiles.retain(|p| {
+                        map.is_lit(map.xy_idx(p.x, p.y))
+                            || DistanceAlg::Pythagoras.distance2d(origin, *p) < 1.5
+                    });
+                }
+
                 // playerなら視界内のタイルを明らかにする
                 let p: Option<&Player> = player.get(ent);
                 if let Some(_p) = p {
@@ -44,3 +55,27 @@ impl<'a> System<'a> for VisibilitySystem {
         }
     }
 }
+
+// 部屋の中にいれば, 部屋を囲む壁まで含めて部屋全体が見える
+// 通路や入口にいるときは隣のタイルだけ
+// monsterも同じ決まりでplayerを探す
+fn room_view(map: &Map, origin: Point) -> Vec<Point> {
+    let mut visible = Vec::new();
+    if let Some(room) = map.room_at(origin.x, origin.y) {
+        for y in room.y1..=room.y2 + 1 {
+            for x in room.x1..=room.x2 + 1 {
+                visible.push(Point::new(x, y));
+            }
+        }
+    }
+
+    for dy in -1..=1 {
+        for dx in -1..=1 {
+            let p = Point::new(origin.x + dx, origin.y + dy);
+            if !visible.contains(&p) {
+                visible.push(p);
+            }
+        }
+    }
+    visible
+}
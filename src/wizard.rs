@@ -0,0 +1,258 @@
+use super::{
+    debug_overlay::{DebugOverlays, OVERLAY_NAMES},
+    spawner, CombatStats, GodMode, Map, Position, State, Viewshed,
+};
+use rltk::Point;
+use specs::prelude::*;
+
+// --wizard で起動したときのデバッグ用コマンド
+// コンソールから打つのと同じことをテストからも呼べるように, worldを受け取る関数にしておく
+
+/// コンソールに打ってる途中の文字
+#[derive(Default)]
+pub struct ConsoleInput {
+    pub text: String,
+}
+
+const HELP: &str =
+    "Commands: spawn <name>, teleport [x y], reveal, hp <n>, god, descend, overlay <name>";
+
+/// 何かを置けるマスか. マップの外, 壁や深い水, monsterや閉じたドアのいるマスはだめ
+fn check_free(map: &Map, point: Point) -> Result<(), String> {
+    if point.x < 0 || point.x >= map.width || point.y < 0 || point.y >= map.height {
+        return Err(format!("({}, {}) is outside the map", point.x, point.y));
+    }
+    if map.blocked[map.xy_idx(point.x, point.y)] {
+        return Err(format!("({}, {}) is blocked", point.x, point.y));
+    }
+    Ok(())
+}
+
+/// 名前のentityをatに出す. 名前は大文字小文字を区別しない
+pub fn spawn(ecs: &mut World, name: &str, at: Point) -> Result<Entity, String> {
+    check_free(&ecs.fetch::<Map>(), at)?;
+    let known = spawner::spawnable_names().find(|n| n.eq_ignore_ascii_case(name.trim()));
+    match known {
+        Some(known) => spawner::spawn_named(ecs, known, at.x, at.y)
+            .ok_or_else(|| format!("Unable to spawn {}", known)),
+        None => Err(format!(
+            "Unknown entity '{}'. Try: {}",
+            name,
+            spawner::spawnable_names().collect::<Vec<_>>().join(", ")
+        )),
+    }
+}
+
+/// playerをtoに動かす. 歩けないマスや, 何かがふさいでるマスには行けない
+pub fn teleport(ecs: &mut World, to: Point) -> Result<(), String> {
+    check_free(&ecs.fetch::<Map>(), to).map_err(|e| format!("Can't teleport: {}", e))?;
+    let player_entity = *ecs.fetch::<Entity>();
+    if let Some(pos) = ecs.write_storage::<Position>().get_mut(player_entity) {
+        pos.x = to.x;
+        pos.y = to.y;
+    }
+    if let Some(viewshed) = ecs.write_storage::<Viewshed>().get_mut(player_entity) {
+        viewshed.dirty = true;
+    }
+    *ecs.write_resource::<Point>() = to;
+    Ok(())
+}
+
+/// フロア全部を見たことにする
+pub fn reveal_map(ecs: &mut World) {
+    let mut map = ecs.write_resource::<Map>();
+    for revealed in map.revealed_tiles.iter_mut() {
+        *revealed = true;
+    }
+}
+
+/// playerのHPを変える. 最大HPより多くしたら最大HPも上げる
+pub fn set_hp(ecs: &mut World, hp: i32) -> Result<(), String> {
+    if hp < 1 {
+        return Err("HP must be at least 1".to_string());
+    }
+    let player_entity = *ecs.fetch::<Entity>();
+    let mut combat_stats = ecs.write_storage::<CombatStats>();
+    let stats = combat_stats
+        .get_mut(player_entity)
+        .ok_or("The player has no stats")?;
+    stats.hp = hp;
+    stats.max_hp = i32::max(stats.max_hp, hp);
+    Ok(())
+}
+
+/// playerの無敵を切り替える. 無敵になったらtrue
+pub fn toggle_god_mode(ecs: &mut World) -> bool {
+    let player_entity = *ecs.fetch::<Entity>();
+    let mut god_mode = ecs.write_storage::<GodMode>();
+    if god_mode.remove(player_entity).is_some() {
+        false
+    } else {
+        god_mode
+            .insert(player_entity, GodMode {})
+            .expect("Unable to insert GodMode");
+        true
+    }
+}
+
+/// 次のフロアをつくってそこに降りる. 新しい深さを返す
+pub fn new_floor(gs: &mut State) -> i32 {
+    let depth = gs.ecs.fetch::<Map>().depth + 1;
+    gs.generate_world_map(depth);
+    depth
+}
+
+/// コンソールに打った1行を実行する. 結果はログに出す文
+/// spawnとteleportは, 場所を書かなければcursorのマスを使う
+pub fn run_command(gs: &mut State, line: &str, cursor: Point) -> String {
+    let mut words = line.split_whitespace();
+    let command = match words.next() {
+        Some(command) => command.to_lowercase(),
+        None => return HELP.to_string(),
+    };
+    let args: Vec<&str> = words.collect();
+
+    let result = match command.as_str() {
+        "spawn" => {
+            let name = args.join(" ");
+            spawn(&mut gs.ecs, &name, cursor).map(|_| format!("Spawned {}", name))
+        }
+        "teleport" | "tp" => {
+            let to = match args.as_slice() {
+                [] => Ok(cursor),
+                [x, y] => match (x.parse::<i32>(), y.parse::<i32>()) {
+                    (Ok(x), Ok(y)) => Ok(Point::new(x, y)),
+                    _ => Err("Usage: teleport [x y]".to_string()),
+                },
+                _ => Err("Usage: teleport [x y]".to_string()),
+            };
+            to.and_then(|to| {
+                teleport(&mut gs.ecs, to).map(|_| format!("Teleported to ({}, {})", to.x, to.y))
+            })
+        }
+        "reveal" => {
+            reveal_map(&mut gs.ecs);
+            Ok("The map is revealed".to_string())
+        }
+        "hp" => match args.first().and_then(|hp| hp.parse().ok()) {
+            Some(hp) => set_hp(&mut gs.ecs, hp).map(|_| format!("HP set to {}", hp)),
+            None => Err("Usage: hp <n>".to_string()),
+        },
+        "god" => {
+            let on = toggle_god_mode(&mut gs.ecs);
+            Ok(format!("God mode {}", if on { "on" } else { "off" }))
+        }
+        "descend" => Ok(format!("Descended to depth {}", new_floor(gs))),
+        "overlay" => match args.first() {
+            Some(name) => gs
+                .ecs
+                .write_resource::<DebugOverlays>()
+                .toggle(name)
+                .map(|on| format!("Overlay {} {}", name, if on { "on" } else { "off" })),
+            None => Err(format!("Usage: overlay <{}>", OVERLAY_NAMES)),
+        },
+        "help" => Ok(HELP.to_string()),
+        _ => Err(format!("Unknown command '{}'. {}", command, HELP)),
+    };
+    match result {
+        Ok(message) | Err(message) => format!("[wizard] {}", message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        describe::FlavourText, keymap::Keymap, map_indexing_system::MapIndexingSystem,
+        settings::Settings, DamageSystem, Name, SufferDamage,
+    };
+
+    fn new_game() -> State {
+        let mut gs = State::new(
+            None,
+            true,
+            Settings::default(),
+            Keymap::default_bindings(),
+            FlavourText::load().unwrap(),
+        );
+        gs.generate_world_map(1);
+        gs
+    }
+
+    // playerのいるマス以外で, 何もふさいでない歩けるマス
+    fn walkable_tile(gs: &State) -> Point {
+        let map = gs.ecs.fetch::<Map>();
+        let player_pos = *gs.ecs.fetch::<Point>();
+        (0..map.tiles.len())
+            .map(|idx| Point::new(idx as i32 % map.width, idx as i32 / map.width))
+            .find(|p| *p != player_pos && !map.blocked[map.xy_idx(p.x, p.y)])
+            .unwrap()
+    }
+
+    #[test]
+    fn spawn_and_teleport() {
+        let mut gs = new_game();
+        let target = walkable_tile(&gs);
+
+        let orc = spawn(&mut gs.ecs, "orc", target).unwrap();
+        assert_eq!(gs.ecs.read_storage::<Name>().get(orc).unwrap().name, "Orc");
+        assert!(spawn(&mut gs.ecs, "dragon", target).is_err());
+
+        // orcがいるマスには, もう何も出せないし飛べない
+        MapIndexingSystem {}.run_now(&gs.ecs);
+        assert!(spawn(&mut gs.ecs, "goblin", target).is_err());
+        assert!(teleport(&mut gs.ecs, target).is_err());
+
+        // 壁の中にも出せない
+        let wall = {
+            let map = gs.ecs.fetch::<Map>();
+            let idx = map.tiles.iter().position(|t| !t.is_walkable()).unwrap();
+            Point::new(idx as i32 % map.width, idx as i32 / map.width)
+        };
+        assert!(spawn(&mut gs.ecs, "orc", wall).is_err());
+        assert!(teleport(&mut gs.ecs, wall).is_err());
+
+        let free = walkable_tile(&gs);
+        teleport(&mut gs.ecs, free).unwrap();
+        assert_eq!(*gs.ecs.fetch::<Point>(), free);
+        assert!(teleport(&mut gs.ecs, Point::new(-1, 0)).is_err());
+    }
+
+    #[test]
+    fn reveal_hp_and_god_mode() {
+        let mut gs = new_game();
+        reveal_map(&mut gs.ecs);
+        assert!(gs.ecs.fetch::<Map>().revealed_tiles.iter().all(|r| *r));
+
+        set_hp(&mut gs.ecs, 500).unwrap();
+        assert!(set_hp(&mut gs.ecs, 0).is_err());
+
+        assert!(toggle_god_mode(&mut gs.ecs));
+        let player = *gs.ecs.fetch::<Entity>();
+        SufferDamage::new_damage(&mut gs.ecs.write_storage(), player, 1000, None);
+        DamageSystem {}.run_now(&gs.ecs);
+        let stats = gs.ecs.read_storage::<CombatStats>();
+        let stats = stats.get(player).unwrap();
+        assert_eq!((stats.hp, stats.max_hp), (500, 500));
+    }
+
+    #[test]
+    fn console_commands() {
+        let mut gs = new_game();
+        let cursor = *gs.ecs.fetch::<Point>();
+        assert_eq!(
+            run_command(&mut gs, "descend", cursor),
+            "[wizard] Descended to depth 2"
+        );
+        assert_eq!(gs.ecs.fetch::<Map>().depth, 2);
+        assert!(run_command(&mut gs, "hp lots", cursor).contains("Usage"));
+        assert!(run_command(&mut gs, "fly", cursor).contains("Unknown command"));
+
+        assert_eq!(
+            run_command(&mut gs, "overlay paths", cursor),
+            "[wizard] Overlay paths on"
+        );
+        assert!(gs.ecs.fetch::<DebugOverlays>().monster_paths);
+        assert!(run_command(&mut gs, "overlay fog", cursor).contains("Unknown overlay"));
+    }
+}